@@ -0,0 +1,477 @@
+//! `#[derive(Encode)]` and `#[derive(Decode)]` for the packets of activitymanager's `mc_protocol`.
+//!
+//! Both share the `#[mc(...)]` attributes, so a type deriving the two of them reads what it writes.
+//! Encode serializes into a `BufMut`, and Decode deserializes both from an `AsyncRead` and from a `Buf` of data in memory.
+//! Fields are encoded in order, each with its own implementation unless one of these attributes says otherwise:
+//! - `#[mc(varint)]` on an `i32` encodes it as a varint
+//! - `#[mc(length_prefixed)]` on a `Vec<u8>` precedes its bytes with their number, as a varint
+//! - `#[mc(max_length = N)]` refuses strings or byte arrays over `N` bytes, when writing and reading them
+//! - `#[mc(optional)]` on an `Option` precedes it with a boolean telling whether it is present.
+//!   The other attributes then apply to the value inside
+//!
+//! Packets start with their ID, given with `#[mc(id = N)]` on structs and on every variant of enums.
+//! The IDs of packets an enum can't deserialize yet are listed on it with
+//! `#[mc(unsupported(encryption_request = 1, ...))]`, so they are told apart from invalid ones.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Fields, GenericArgument,
+    Ident, LitInt, LitStr, PathArguments, Type,
+};
+
+#[proc_macro_derive(Encode, attributes(mc))]
+pub fn derive_encode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input, Direction::Encode)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(Decode, attributes(mc))]
+pub fn derive_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input, Direction::Decode)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+enum Direction {
+    Encode,
+    Decode,
+}
+
+/// Where the generated code finds the protocol, which works inside activitymanager too thanks to `extern crate self`
+fn protocol() -> TokenStream2 {
+    quote!(::activitymanager::mc_protocol)
+}
+
+fn support() -> TokenStream2 {
+    quote!(::activitymanager::mc_protocol::derive_support)
+}
+
+#[derive(Default)]
+struct FieldOptions {
+    varint: bool,
+    length_prefixed: bool,
+    optional: bool,
+    max_length: Option<LitInt>,
+}
+
+#[derive(Default)]
+struct ItemOptions {
+    id: Option<LitInt>,
+    unsupported: Vec<(LitInt, LitStr)>,
+}
+
+fn mc_attributes(attributes: &[Attribute]) -> impl Iterator<Item = &Attribute> {
+    attributes
+        .iter()
+        .filter(|attribute| attribute.path().is_ident("mc"))
+}
+
+fn field_options(attributes: &[Attribute]) -> syn::Result<FieldOptions> {
+    let mut options = FieldOptions::default();
+    for attribute in mc_attributes(attributes) {
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("varint") {
+                options.varint = true;
+            } else if meta.path.is_ident("length_prefixed") {
+                options.length_prefixed = true;
+            } else if meta.path.is_ident("optional") {
+                options.optional = true;
+            } else if meta.path.is_ident("max_length") {
+                options.max_length = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error(
+                    "expected `varint`, `length_prefixed`, `optional` or `max_length = N`",
+                ));
+            }
+            Ok(())
+        })?;
+
+        if options.varint && options.length_prefixed {
+            return Err(syn::Error::new(
+                attribute.span(),
+                "a field can't be both a varint and length prefixed",
+            ));
+        }
+    }
+    Ok(options)
+}
+
+fn item_options(attributes: &[Attribute]) -> syn::Result<ItemOptions> {
+    let mut options = ItemOptions::default();
+    for attribute in mc_attributes(attributes) {
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                options.id = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("unsupported") {
+                meta.parse_nested_meta(|packet| {
+                    let name = packet
+                        .path
+                        .get_ident()
+                        .ok_or_else(|| packet.error("expected the name of a packet"))?;
+                    let name = LitStr::new(
+                        &format!("{} packets", name.to_string().replace('_', " ")),
+                        name.span(),
+                    );
+                    options.unsupported.push((packet.value()?.parse()?, name));
+                    Ok(())
+                })?;
+            } else {
+                return Err(meta.error("expected `id = N` or `unsupported(packet_name = N, ...)`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+/// The `T` of an `Option<T>`
+fn option_inner(ty: &Type) -> syn::Result<&Type> {
+    if let Type::Path(path) = ty {
+        if let Some(segment) = path.path.segments.last() {
+            if segment.ident == "Option" {
+                if let PathArguments::AngleBracketed(arguments) = &segment.arguments {
+                    if let Some(GenericArgument::Type(inner)) = arguments.args.first() {
+                        return Ok(inner);
+                    }
+                }
+            }
+        }
+    }
+    Err(syn::Error::new(
+        ty.span(),
+        "`optional` fields must be an `Option`",
+    ))
+}
+
+/// A field of a struct or variant
+struct Field {
+    name: Ident,
+    ty: Type,
+    options: FieldOptions,
+}
+
+fn fields(fields: &Fields) -> syn::Result<Vec<Field>> {
+    match fields {
+        Fields::Named(named) => named
+            .named
+            .iter()
+            .map(|field| {
+                Ok(Field {
+                    name: field.ident.clone().expect("named fields have a name"),
+                    ty: field.ty.clone(),
+                    options: field_options(&field.attrs)?,
+                })
+            })
+            .collect(),
+        Fields::Unit => Ok(Vec::new()),
+        Fields::Unnamed(unnamed) => Err(syn::Error::new(
+            unnamed.span(),
+            "packets can only have named fields",
+        )),
+    }
+}
+
+/// Puts the field bound to its name, which is a reference, in the buffer
+fn write_field(field: &Field) -> TokenStream2 {
+    let (protocol, support) = (protocol(), support());
+    let label = field.name.to_string();
+    let options = &field.options;
+
+    let write_value = |value: TokenStream2| {
+        let check = options
+            .max_length
+            .as_ref()
+            .map(|max| quote!(#support::check_length(#label, #value.len(), #max)?;));
+        let write = if options.varint {
+            quote!(#support::put_varint(buf, *#value)?;)
+        } else if options.length_prefixed {
+            quote!(#support::put_bytes(buf, #value)?;)
+        } else {
+            quote!(#protocol::Encode::serialize_buf(#value, buf)?;)
+        };
+        quote!(#check #write)
+    };
+
+    let name = &field.name;
+    if options.optional {
+        let write_inner = write_value(quote!(value));
+        quote! {
+            #support::put_bool(buf, #name.is_some())?;
+            if let Some(value) = #name {
+                #write_inner
+            }
+        }
+    } else {
+        write_value(quote!(#name))
+    }
+}
+
+/// Where a type is deserialized from, which decides the helpers its deserialization calls
+#[derive(Clone, Copy)]
+enum Source {
+    /// An `AsyncRead` named `reader`
+    Reader,
+    /// A `Buf` named `buf`
+    Buf,
+}
+
+impl Source {
+    /// Calls the helper of `derive_support` reading from this source, named `read_*` or `get_*`
+    fn call(self, helper: &str) -> TokenStream2 {
+        let support = support();
+        match self {
+            Self::Reader => {
+                let helper = Ident::new(&format!("read_{helper}"), proc_macro2::Span::call_site());
+                quote!(#support::#helper(reader).await?)
+            }
+            Self::Buf => {
+                let helper = Ident::new(&format!("get_{helper}"), proc_macro2::Span::call_site());
+                quote!(#support::#helper(buf)?)
+            }
+        }
+    }
+
+    fn decode(self, ty: &Type) -> TokenStream2 {
+        let protocol = protocol();
+        match self {
+            Self::Reader => quote!(<#ty as #protocol::Decode>::deserialize_read(reader).await?),
+            Self::Buf => quote!(<#ty as #protocol::Decode>::deserialize_buf(buf)?),
+        }
+    }
+
+    fn expect_id(self, id: &LitInt, label: &str) -> TokenStream2 {
+        let support = support();
+        match self {
+            Self::Reader => quote!(#support::expect_id(reader, #id, #label).await?;),
+            Self::Buf => quote!(#support::expect_id_buf(buf, #id, #label)?;),
+        }
+    }
+}
+
+/// Reads the field into a variable named after it
+fn read_field(field: &Field, source: Source) -> syn::Result<TokenStream2> {
+    let support = support();
+    let label = field.name.to_string();
+    let options = &field.options;
+
+    let value_ty = if options.optional {
+        option_inner(&field.ty)?
+    } else {
+        &field.ty
+    };
+    let read = if options.varint {
+        source.call("varint")
+    } else if options.length_prefixed {
+        source.call("bytes")
+    } else {
+        source.decode(value_ty)
+    };
+    let check = options
+        .max_length
+        .as_ref()
+        .map(|max| quote!(#support::check_length(#label, value.len(), #max)?;));
+    let read_value = quote!({
+        let value: #value_ty = #read;
+        #check
+        value
+    });
+
+    let (name, ty) = (&field.name, &field.ty);
+    let present = source.call("bool");
+    Ok(if options.optional {
+        quote! {
+            let #name: #ty = if #present {
+                Some(#read_value)
+            } else {
+                None
+            };
+        }
+    } else {
+        quote!(let #name: #ty = #read_value;)
+    })
+}
+
+/// The pattern or expression listing the fields of a struct or variant
+fn construct(path: TokenStream2, fields: &[Field], unit: bool) -> TokenStream2 {
+    let names = fields.iter().map(|field| &field.name);
+    if unit {
+        path
+    } else {
+        quote!(#path { #(#names),* })
+    }
+}
+
+/// The body deserializing the struct or enum from `source`
+fn deserialize(
+    input: &DeriveInput,
+    item: &ItemOptions,
+    source: Source,
+) -> syn::Result<TokenStream2> {
+    let support = support();
+    let label = input.ident.to_string();
+
+    match &input.data {
+        Data::Struct(data) => {
+            let unit = matches!(data.fields, Fields::Unit);
+            let fields = fields(&data.fields)?;
+            let reads = fields
+                .iter()
+                .map(|field| read_field(field, source))
+                .collect::<syn::Result<Vec<_>>>()?;
+            let read_id = item.id.as_ref().map(|id| source.expect_id(id, &label));
+            let pattern = construct(quote!(Self), &fields, unit);
+
+            Ok(quote! {
+                #read_id
+                #(#reads)*
+                Ok(#pattern)
+            })
+        }
+        Data::Enum(data) => {
+            let mut read_arms = Vec::new();
+            for variant in &data.variants {
+                let id = variant_id(variant)?;
+                let unit = matches!(variant.fields, Fields::Unit);
+                let fields = fields(&variant.fields)?;
+                let variant_name = &variant.ident;
+                let pattern = construct(quote!(Self::#variant_name), &fields, unit);
+                let reads = fields
+                    .iter()
+                    .map(|field| read_field(field, source))
+                    .collect::<syn::Result<Vec<_>>>()?;
+
+                read_arms.push(quote! {
+                    #id => {
+                        #(#reads)*
+                        Ok(#pattern)
+                    }
+                });
+            }
+            let unsupported = item
+                .unsupported
+                .iter()
+                .map(|(id, name)| quote!(#id => Err(#support::unsupported(#name)),));
+            let read_id = source.call("id");
+
+            Ok(quote! {
+                match #read_id {
+                    #(#read_arms)*
+                    #(#unsupported)*
+                    other => Err(#support::unexpected_id(#label, other)),
+                }
+            })
+        }
+        Data::Union(data) => Err(syn::Error::new(
+            data.union_token.span(),
+            "packets can't be unions",
+        )),
+    }
+}
+
+/// The body serializing the struct or enum into `buf`
+fn serialize(input: &DeriveInput, item: &ItemOptions) -> syn::Result<TokenStream2> {
+    let support = support();
+
+    match &input.data {
+        Data::Struct(data) => {
+            let unit = matches!(data.fields, Fields::Unit);
+            let fields = fields(&data.fields)?;
+            let writes = fields.iter().map(write_field);
+            let write_id = item
+                .id
+                .as_ref()
+                .map(|id| quote!(#support::put_id(buf, #id)?;));
+            let pattern = construct(quote!(Self), &fields, unit);
+
+            Ok(quote! {
+                #write_id
+                let #pattern = self;
+                #(#writes)*
+                Ok(())
+            })
+        }
+        Data::Enum(data) => {
+            let mut write_arms = Vec::new();
+            for variant in &data.variants {
+                let id = variant_id(variant)?;
+                let unit = matches!(variant.fields, Fields::Unit);
+                let fields = fields(&variant.fields)?;
+                let variant_name = &variant.ident;
+                let pattern = construct(quote!(Self::#variant_name), &fields, unit);
+                let writes = fields.iter().map(write_field);
+
+                write_arms.push(quote! {
+                    #pattern => {
+                        #support::put_id(buf, #id)?;
+                        #(#writes)*
+                    }
+                });
+            }
+
+            Ok(quote! {
+                match self {
+                    #(#write_arms)*
+                }
+                Ok(())
+            })
+        }
+        Data::Union(data) => Err(syn::Error::new(
+            data.union_token.span(),
+            "packets can't be unions",
+        )),
+    }
+}
+
+fn variant_id(variant: &syn::Variant) -> syn::Result<LitInt> {
+    item_options(&variant.attrs)?.id.ok_or_else(|| {
+        syn::Error::new(
+            variant.span(),
+            "every variant needs a packet ID: `#[mc(id = N)]`",
+        )
+    })
+}
+
+fn expand(input: DeriveInput, direction: Direction) -> syn::Result<TokenStream2> {
+    let (protocol, support) = (protocol(), support());
+    let name = &input.ident;
+    let item = item_options(&input.attrs)?;
+
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(match direction {
+        // Writing to a stream is provided by the trait, on top of this
+        Direction::Encode => {
+            let serialize = serialize(&input, &item)?;
+            quote! {
+                impl #impl_generics #protocol::Encode for #name #type_generics #where_clause {
+                    fn serialize_buf<B: #support::BufMut>(&self, buf: &mut B) -> #support::io::Result<()> {
+                        #serialize
+                    }
+                }
+            }
+        }
+        Direction::Decode => {
+            let deserialize_read = deserialize(&input, &item, Source::Reader)?;
+            let deserialize_buf = deserialize(&input, &item, Source::Buf)?;
+            quote! {
+                #[#support::async_trait]
+                impl #impl_generics #protocol::Decode for #name #type_generics #where_clause {
+                    async fn deserialize_read<R>(reader: &mut R) -> #support::io::Result<Self>
+                    where
+                        R: #support::io::AsyncRead + Unpin + Send,
+                    {
+                        #deserialize_read
+                    }
+
+                    fn deserialize_buf<B: #support::Buf>(buf: &mut B) -> #support::io::Result<Self> {
+                        #deserialize_buf
+                    }
+                }
+            }
+        }
+    })
+}
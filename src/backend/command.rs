@@ -0,0 +1,202 @@
+use std::{
+    process::{ExitStatus, Stdio},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::{io, process::Command, time::Instant};
+
+use super::{Backend, Exit};
+
+/// Manages the minecraft server through user provided commands
+pub struct CommandBackend {
+    start: String,
+    stop: String,
+    status: String,
+    console: Option<String>,
+    /// The freeze and resume commands
+    freeze: Option<(String, String)>,
+    status_interval: Duration,
+    /// When `wait` should next run the status command. Kept here so `wait` stays cancel safe
+    next_status: Instant,
+}
+
+impl CommandBackend {
+    pub fn new(
+        start: String,
+        stop: String,
+        status: String,
+        console: Option<String>,
+        freeze: Option<(String, String)>,
+        status_interval: Duration,
+    ) -> Self {
+        Self {
+            start,
+            stop,
+            status,
+            console,
+            freeze,
+            status_interval,
+            next_status: Instant::now(),
+        }
+    }
+}
+
+async fn run(command: &str, args: &[&str]) -> io::Result<ExitStatus> {
+    Command::new("/bin/sh")
+        .args(["-c", command, "sh"])
+        .args(args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await
+}
+
+/// Runs `command` and turns a failure status into an error
+async fn run_checked(command: &str, args: &[&str]) -> io::Result<()> {
+    let status = run(command, args).await?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("'{command}' failed on {status}")))
+    }
+}
+
+#[async_trait]
+impl Backend for CommandBackend {
+    async fn start(&mut self) -> io::Result<()> {
+        run_checked(&self.start, &[]).await?;
+        self.next_status = Instant::now() + self.status_interval;
+        Ok(())
+    }
+
+    async fn request_stop(&mut self) -> io::Result<()> {
+        run_checked(&self.stop, &[]).await?;
+        // Notice the stop quickly
+        self.next_status = Instant::now();
+        Ok(())
+    }
+
+    async fn wait(&mut self) -> Exit {
+        loop {
+            tokio::time::sleep_until(self.next_status).await;
+            let running = self.is_running().await;
+            self.next_status = Instant::now() + self.status_interval;
+            match running {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Exit {
+                        success: true,
+                        status: "reported as stopped by the status command".to_owned(),
+                    }
+                }
+                Err(err) => {
+                    println!("\x1b[38;5;11mWarning: Couldn't run the status command. Got err: {err}\x1b[0m");
+                }
+            }
+        }
+    }
+
+    async fn is_running(&mut self) -> io::Result<bool> {
+        Ok(run(&self.status, &[]).await?.success())
+    }
+
+    async fn send_command(&mut self, command: &str) -> io::Result<()> {
+        match self.console {
+            Some(ref console) => run_checked(console, &[command]).await,
+            None => Err(io::Error::other(
+                "no --console-command was given to forward console commands",
+            )),
+        }
+    }
+
+    async fn freeze(&mut self) -> io::Result<()> {
+        match self.freeze {
+            Some((ref freeze, _)) => run_checked(freeze, &[]).await,
+            None => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "no --freeze-command was given",
+            )),
+        }
+    }
+
+    async fn resume(&mut self) -> io::Result<()> {
+        match self.freeze {
+            Some((_, ref resume)) => run_checked(resume, &[]).await,
+            None => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "no --resume-command was given",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_backend(status: &str, console: Option<&str>) -> CommandBackend {
+        CommandBackend::new(
+            "true".to_owned(),
+            "true".to_owned(),
+            status.to_owned(),
+            console.map(str::to_owned),
+            None,
+            Duration::from_millis(50),
+        )
+    }
+
+    #[tokio::test]
+    async fn run_checked_test() {
+        assert!(run_checked("true", &[]).await.is_ok());
+        assert!(run_checked("false", &[]).await.is_err());
+        assert!(run_checked("exit 1", &[]).await.is_err());
+        assert!(run_checked(r#"test "$1" = say"#, &["say"]).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn wait_test() {
+        // Running for as long as the file exists
+        let file =
+            std::env::temp_dir().join(format!("activitymanager-running-{}", std::process::id()));
+        let file = file.to_str().unwrap();
+        let mut backend = CommandBackend::new(
+            format!("touch {file}"),
+            format!("rm {file}"),
+            format!("test -e {file}"),
+            None,
+            None,
+            Duration::from_millis(50),
+        );
+
+        backend.start().await.unwrap();
+        assert!(backend.is_running().await.unwrap());
+        // Still running
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), backend.wait())
+                .await
+                .is_err()
+        );
+
+        backend.request_stop().await.unwrap();
+        let exit = tokio::time::timeout(Duration::from_secs(1), backend.wait())
+            .await
+            .unwrap();
+        assert!(exit.success);
+        assert!(!backend.is_running().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn send_command_test() {
+        let mut backend = new_backend("true", Some(r#"test "$1" = "say hi""#));
+        assert!(backend.send_command("say hi").await.is_ok());
+        assert!(backend.send_command("stop").await.is_err());
+
+        let mut backend = new_backend("true", None);
+        assert!(backend.send_command("say hi").await.is_err());
+        assert_eq!(
+            io::ErrorKind::Unsupported,
+            backend.freeze().await.unwrap_err().kind()
+        );
+    }
+}
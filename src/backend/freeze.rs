@@ -0,0 +1,136 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use nix::{
+    errno::Errno,
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+use tokio::io;
+
+/// `root` and all of its descendants, found through /proc
+fn process_tree(root: u32) -> io::Result<Vec<u32>> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for entry in fs::read_dir("/proc")? {
+        let Some(pid) = entry?.file_name().to_str().and_then(|pid| pid.parse().ok()) else {
+            continue;
+        };
+        // Processes may exit while we look at them
+        let Ok(stat) = fs::read_to_string(format!("/proc/{pid}/stat")) else {
+            continue;
+        };
+        // The parent pid comes after the state, which follows the command name in parentheses
+        let parent = stat
+            .rsplit_once(')')
+            .and_then(|(_, rest)| rest.split_whitespace().nth(1))
+            .and_then(|parent| parent.parse().ok());
+        if let Some(parent) = parent {
+            children.entry(parent).or_default().push(pid);
+        }
+    }
+
+    let mut tree = vec![root];
+    let mut i = 0;
+    while let Some(pid) = tree.get(i) {
+        tree.extend(children.remove(pid).unwrap_or_default());
+        i += 1;
+    }
+    Ok(tree)
+}
+
+/// Sends `signal` to `root` and all of its descendants
+pub fn signal_tree(root: u32, signal: Signal) -> io::Result<()> {
+    for pid in process_tree(root)? {
+        match kill(Pid::from_raw(pid as i32), signal) {
+            // It exited since we listed it
+            Ok(()) | Err(Errno::ESRCH) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Moves `pid` into the cgroup v2 directory `cgroup`. Processes it starts later end up there too
+pub fn join_cgroup(cgroup: &Path, pid: u32) -> io::Result<()> {
+    fs::write(cgroup.join("cgroup.procs"), pid.to_string())
+}
+
+pub fn set_cgroup_frozen(cgroup: &Path, frozen: bool) -> io::Result<()> {
+    fs::write(cgroup.join("cgroup.freeze"), if frozen { "1" } else { "0" })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{process::Command, thread, time::Duration};
+
+    use super::*;
+
+    /// The state letter of `pid`, e.g. `T` when it is stopped
+    fn state(pid: u32) -> String {
+        let stat = fs::read_to_string(format!("/proc/{pid}/stat")).unwrap();
+        let (_, rest) = stat.rsplit_once(')').unwrap();
+        rest.split_whitespace().next().unwrap().to_owned()
+    }
+
+    /// Polls `condition` for up to 5s, as processes don't change state right away
+    fn eventually(mut condition: impl FnMut() -> bool) -> bool {
+        (0..100).any(|_| {
+            condition() || {
+                thread::sleep(Duration::from_millis(50));
+                false
+            }
+        })
+    }
+
+    #[test]
+    fn signal_tree_test() {
+        let mut shell = Command::new("/bin/sh")
+            .args(["-c", "sleep 60 & wait"])
+            .spawn()
+            .unwrap();
+        let root = shell.id();
+        let mut tree = Vec::new();
+        assert!(eventually(|| {
+            tree = process_tree(root).unwrap();
+            tree.len() == 2
+        }));
+        assert_eq!(root, tree[0]);
+
+        signal_tree(root, Signal::SIGSTOP).unwrap();
+        assert!(eventually(|| tree.iter().all(|&pid| state(pid) == "T")));
+        signal_tree(root, Signal::SIGCONT).unwrap();
+        assert!(eventually(|| tree.iter().all(|&pid| state(pid) != "T")));
+
+        signal_tree(root, Signal::SIGKILL).unwrap();
+        assert!(!shell.wait().unwrap().success());
+        // Exited processes are left alone
+        signal_tree(root, Signal::SIGCONT).unwrap();
+    }
+
+    #[test]
+    fn cgroup_test() {
+        // The cgroup interface files, in a plain directory rather than the cgroup filesystem
+        let cgroup = std::env::temp_dir().join(format!(
+            "activitymanager-cgroup-test-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&cgroup).unwrap();
+
+        join_cgroup(&cgroup, 1234).unwrap();
+        assert_eq!(
+            "1234",
+            fs::read_to_string(cgroup.join("cgroup.procs")).unwrap()
+        );
+        set_cgroup_frozen(&cgroup, true).unwrap();
+        assert_eq!(
+            "1",
+            fs::read_to_string(cgroup.join("cgroup.freeze")).unwrap()
+        );
+        set_cgroup_frozen(&cgroup, false).unwrap();
+        assert_eq!(
+            "0",
+            fs::read_to_string(cgroup.join("cgroup.freeze")).unwrap()
+        );
+
+        fs::remove_dir_all(&cgroup).unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+use std::{path::PathBuf, process::Stdio};
+
+use async_trait::async_trait;
+use nix::sys::signal::Signal;
+use tokio::{
+    io::{self, AsyncWriteExt},
+    process::{Child, ChildStdin, Command},
+};
+
+use super::{freeze, Backend, Exit};
+
+/// Runs the start script as a child process, with its stdin used as the console
+pub struct LocalBackend {
+    start_script: PathBuf,
+    /// Frozen with the cgroup freezer instead of signals when set
+    freeze_cgroup: Option<PathBuf>,
+    child: Option<Child>,
+    stdin: Option<ChildStdin>,
+}
+
+impl LocalBackend {
+    pub fn new(start_script: PathBuf, freeze_cgroup: Option<PathBuf>) -> Self {
+        Self {
+            start_script,
+            freeze_cgroup,
+            child: None,
+            stdin: None,
+        }
+    }
+
+    fn pid(&self) -> io::Result<u32> {
+        self.child
+            .as_ref()
+            .and_then(Child::id)
+            .ok_or_else(not_running)
+    }
+}
+
+fn not_running() -> io::Error {
+    io::Error::other("the minecraft server isn't running")
+}
+
+#[async_trait]
+impl Backend for LocalBackend {
+    async fn start(&mut self) -> io::Result<()> {
+        let mut child = Command::new("/bin/bash")
+            .args([self.start_script.as_os_str()])
+            .stdin(Stdio::piped())
+            .spawn()?;
+        if let (Some(cgroup), Some(pid)) = (&self.freeze_cgroup, child.id()) {
+            if let Err(err) = freeze::join_cgroup(cgroup, pid) {
+                println!("\x1b[38;5;11mWarning: Couldn't move the minecraft server to {}. Got err: {err}\x1b[0m", cgroup.display());
+            }
+        }
+        self.stdin = child.stdin.take();
+        self.child = Some(child);
+        Ok(())
+    }
+
+    async fn request_stop(&mut self) -> io::Result<()> {
+        self.send_command("stop").await?;
+        // The server has nothing left to read
+        self.stdin = None;
+        Ok(())
+    }
+
+    async fn wait(&mut self) -> Exit {
+        let exit_status = match self.child {
+            Some(ref mut child) => child.wait().await,
+            None => Err(not_running()),
+        };
+        self.child = None;
+        self.stdin = None;
+        match exit_status {
+            Ok(status) => Exit {
+                success: status.success(),
+                status: status.to_string(),
+            },
+            Err(err) => Exit {
+                success: false,
+                status: err.to_string(),
+            },
+        }
+    }
+
+    async fn is_running(&mut self) -> io::Result<bool> {
+        match self.child {
+            Some(ref mut child) => Ok(child.try_wait()?.is_none()),
+            None => Ok(false),
+        }
+    }
+
+    async fn send_command(&mut self, command: &str) -> io::Result<()> {
+        let stdin = self.stdin.as_mut().ok_or_else(not_running)?;
+        stdin.write_all(format!("{command}\n").as_bytes()).await?;
+        stdin.flush().await
+    }
+
+    async fn freeze(&mut self) -> io::Result<()> {
+        match self.freeze_cgroup {
+            Some(ref cgroup) => freeze::set_cgroup_frozen(cgroup, true),
+            None => freeze::signal_tree(self.pid()?, Signal::SIGSTOP),
+        }
+    }
+
+    async fn resume(&mut self) -> io::Result<()> {
+        match self.freeze_cgroup {
+            Some(ref cgroup) => freeze::set_cgroup_frozen(cgroup, false),
+            None => freeze::signal_tree(self.pid()?, Signal::SIGCONT),
+        }
+    }
+
+    async fn kill(&mut self) -> io::Result<()> {
+        // SIGKILL reaches stopped processes too, and those in a frozen cgroup
+        freeze::signal_tree(self.pid()?, Signal::SIGKILL)?;
+        self.stdin = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use super::*;
+
+    /// A start script running until it is killed, in a directory of its own
+    fn start_script(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("activitymanager-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("start.sh");
+        fs::write(&script, "sleep 60\n").unwrap();
+        script
+    }
+
+    fn is_stopped(pid: u32) -> bool {
+        let stat = fs::read_to_string(format!("/proc/{pid}/stat")).unwrap();
+        stat.rsplit_once(')')
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            == Some("T")
+    }
+
+    #[tokio::test]
+    async fn signal_freeze_test() {
+        let script = start_script("signal-freeze-test");
+        let mut backend = LocalBackend::new(script.clone(), None);
+        backend.start().await.unwrap();
+        let pid = backend.pid().unwrap();
+
+        backend.freeze().await.unwrap();
+        let mut stopped = false;
+        for _ in 0..100 {
+            stopped = is_stopped(pid);
+            if stopped {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(stopped);
+
+        // Without resuming it first
+        backend.kill().await.unwrap();
+        assert!(!backend.wait().await.success);
+        assert!(!backend.is_running().await.unwrap());
+
+        fs::remove_dir_all(script.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cgroup_freeze_test() {
+        let script = start_script("cgroup-freeze-test");
+        // The cgroup interface files, in a plain directory rather than the cgroup filesystem
+        let cgroup = script.parent().unwrap().to_owned();
+        let mut backend = LocalBackend::new(script, Some(cgroup.clone()));
+        backend.start().await.unwrap();
+        let pid = backend.pid().unwrap();
+        assert_eq!(
+            pid.to_string(),
+            fs::read_to_string(cgroup.join("cgroup.procs")).unwrap()
+        );
+
+        // The cgroup is frozen rather than the processes signalled
+        backend.freeze().await.unwrap();
+        assert_eq!(
+            "1",
+            fs::read_to_string(cgroup.join("cgroup.freeze")).unwrap()
+        );
+        assert!(!is_stopped(pid));
+        backend.resume().await.unwrap();
+        assert_eq!(
+            "0",
+            fs::read_to_string(cgroup.join("cgroup.freeze")).unwrap()
+        );
+
+        backend.kill().await.unwrap();
+        assert!(!backend.wait().await.success);
+
+        fs::remove_dir_all(&cgroup).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+mod command;
+mod freeze;
+mod local;
+
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use clap::Args;
+use tokio::io;
+
+use crate::duration::parse_duration;
+pub use command::CommandBackend;
+pub use local::LocalBackend;
+
+/// How the minecraft server exited
+pub struct Exit {
+    pub success: bool,
+    pub status: String,
+}
+
+impl std::fmt::Display for Exit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.status)
+    }
+}
+
+/// Something that runs the minecraft server for us
+#[async_trait]
+pub trait Backend: Send {
+    async fn start(&mut self) -> io::Result<()>;
+
+    /// Asks the minecraft server to stop. `wait` returns once it did
+    async fn request_stop(&mut self) -> io::Result<()>;
+
+    /// Waits for the minecraft server to exit, whether it was asked to or not.
+    /// Must be cancel safe, as it is raced against other events.
+    async fn wait(&mut self) -> Exit;
+
+    async fn is_running(&mut self) -> io::Result<bool>;
+
+    /// Sends a line to the console of the minecraft server
+    async fn send_command(&mut self, command: &str) -> io::Result<()>;
+
+    /// Suspends the minecraft server without stopping it, so it can be resumed right away
+    async fn freeze(&mut self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "this backend can't freeze the minecraft server",
+        ))
+    }
+
+    async fn resume(&mut self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "this backend can't resume the minecraft server",
+        ))
+    }
+
+    /// Stops the minecraft server even while it is frozen and can't handle a stop command. `wait` returns once it did.
+    /// Backends that can't kill it ask it to stop instead.
+    async fn kill(&mut self) -> io::Result<()> {
+        self.request_stop().await
+    }
+}
+
+/// Commands managing a minecraft server that isn't a child of activity manager, e.g. in a container or on another host.
+/// They are run with /bin/sh.
+#[derive(Args, Debug)]
+pub struct BackendArgs {
+    /// command starting the minecraft server, used instead of the start script
+    #[arg(long, value_name = "COMMAND", conflicts_with = "start_script", requires_all = ["stop_command", "status_command"])]
+    start_command: Option<String>,
+
+    /// command asking the minecraft server to stop
+    #[arg(long, value_name = "COMMAND", requires = "start_command")]
+    stop_command: Option<String>,
+
+    /// command exiting successfully if and only if the minecraft server is running
+    #[arg(long, value_name = "COMMAND", requires = "start_command")]
+    status_command: Option<String>,
+
+    /// command sending a console command to the minecraft server, which is passed as $1
+    #[arg(long, value_name = "COMMAND", requires = "start_command")]
+    console_command: Option<String>,
+
+    /// command suspending the minecraft server, for --idle-action freeze
+    #[arg(long, value_name = "COMMAND", requires = "start_command")]
+    freeze_command: Option<String>,
+
+    /// command resuming the minecraft server after the freeze command
+    #[arg(long, value_name = "COMMAND", requires = "freeze_command")]
+    resume_command: Option<String>,
+
+    /// cgroup v2 directory the start script is moved to, so it can be frozen with the cgroup freezer.
+    /// Without it, the process tree of the start script is frozen with SIGSTOP
+    #[arg(long, value_name = "PATH", conflicts_with = "start_command")]
+    freeze_cgroup: Option<PathBuf>,
+
+    /// how often the status command is run to notice the minecraft server exited
+    #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = parse_duration)]
+    status_interval: std::time::Duration,
+}
+
+/// The command backend when commands are given, otherwise the start script run as a child process
+pub fn from_args(start_script: Option<PathBuf>, args: BackendArgs) -> Box<dyn Backend> {
+    match (args.start_command, args.stop_command, args.status_command) {
+        (Some(start), Some(stop), Some(status)) => Box::new(CommandBackend::new(
+            start,
+            stop,
+            status,
+            args.console_command,
+            args.freeze_command.zip(args.resume_command),
+            args.status_interval,
+        )),
+        _ => Box::new(LocalBackend::new(
+            start_script
+                .expect("clap should require a start script when no start command is given"),
+            args.freeze_cgroup,
+        )),
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use chrono::{Datelike, Local, NaiveDateTime};
+use clap::Args;
+use flate2::{write::GzEncoder, Compression};
+use tokio::task::{self, JoinHandle};
+
+use crate::duration::format_duration;
+
+const ARCHIVE_PREFIX: &str = "backup-";
+const ARCHIVE_SUFFIX: &str = ".tar.gz";
+const ARCHIVE_TIME_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+
+/// Backups of the worlds of the minecraft server, taken while it is stopped
+#[derive(Args, Debug)]
+pub struct BackupArgs {
+    /// if set, the worlds found in the server root are archived into this folder after every idle stop
+    #[arg(long, value_name = "PATH", requires = "server_root")]
+    backup_dir: Option<PathBuf>,
+
+    /// number of most recent backups to keep
+    #[arg(long, value_name = "COUNT", default_value_t = 5)]
+    backup_keep_last: usize,
+
+    /// also keep the most recent backup of each of the last COUNT days that have one
+    #[arg(long, value_name = "COUNT", default_value_t = 0)]
+    backup_keep_daily: usize,
+
+    /// also keep the most recent backup of each of the last COUNT weeks that have one
+    #[arg(long, value_name = "COUNT", default_value_t = 0)]
+    backup_keep_weekly: usize,
+}
+
+impl BackupArgs {
+    /// Starts backing up the worlds of `server_root` in the background, if backups are enabled
+    pub fn start(&self, server_root: &Path) -> Option<JoinHandle<()>> {
+        let backup_dir = self.backup_dir.clone()?;
+        let server_root = server_root.to_owned();
+        let retention = Retention {
+            last: self.backup_keep_last,
+            daily: self.backup_keep_daily,
+            weekly: self.backup_keep_weekly,
+        };
+
+        println!("\x1b[38;5;14mBacking up the worlds of the minecraft server\x1b[0m");
+
+        Some(task::spawn_blocking(move || {
+            let start = Instant::now();
+            match backup(&server_root, &backup_dir) {
+                Ok(archive) => println!(
+                    "\x1b[38;5;14mBacked up the worlds to {} in {}\x1b[0m",
+                    archive.display(),
+                    format_duration(start.elapsed())
+                ),
+                Err(err) => {
+                    println!(
+                        "\x1b[38;5;11mWarning: Couldn't back up the worlds. Got err: {err}\x1b[0m"
+                    );
+                    return;
+                }
+            }
+
+            if let Err(err) = prune(&backup_dir, retention) {
+                println!(
+                    "\x1b[38;5;11mWarning: Couldn't delete old backups. Got err: {err}\x1b[0m"
+                );
+            }
+        }))
+    }
+}
+
+/// Worlds are the folders of the server root holding a `level.dat`
+fn worlds(server_root: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut worlds = Vec::new();
+    for entry in fs::read_dir(server_root)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() && entry.path().join("level.dat").is_file() {
+            worlds.push((
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.path(),
+            ));
+        }
+    }
+    worlds.sort();
+    Ok(worlds)
+}
+
+/// Archives every world into a new timestamped archive and returns its path
+fn backup(server_root: &Path, backup_dir: &Path) -> io::Result<PathBuf> {
+    let worlds = worlds(server_root)?;
+    if worlds.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no world found in {}", server_root.display()),
+        ));
+    }
+
+    fs::create_dir_all(backup_dir)?;
+    let name = format!(
+        "{ARCHIVE_PREFIX}{}{ARCHIVE_SUFFIX}",
+        Local::now().format(ARCHIVE_TIME_FORMAT)
+    );
+    let archive = backup_dir.join(&name);
+    // Written under another name first so that an interrupted backup is never mistaken for a complete one
+    let partial = backup_dir.join(format!("{name}.partial"));
+
+    let result = (|| {
+        let file = fs::File::create(&partial)?;
+        let mut builder =
+            tar::Builder::new(GzEncoder::new(BufWriter::new(file), Compression::default()));
+        for (name, path) in worlds {
+            builder.append_dir_all(name, path)?;
+        }
+        builder
+            .into_inner()?
+            .finish()?
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()
+    })();
+
+    match result {
+        Ok(()) => fs::rename(&partial, &archive).map(|_| archive),
+        Err(err) => {
+            let _ = fs::remove_file(&partial);
+            Err(err)
+        }
+    }
+}
+
+/// Which backups survive pruning
+#[derive(Debug, Clone, Copy)]
+struct Retention {
+    last: usize,
+    daily: usize,
+    weekly: usize,
+}
+
+impl Retention {
+    /// Returns the times among `times` (sorted from newest to oldest) that should be kept
+    fn keep(&self, times: &[NaiveDateTime]) -> HashSet<NaiveDateTime> {
+        let mut keep: HashSet<NaiveDateTime> = times.iter().take(self.last).copied().collect();
+
+        let mut keep_newest_per = |count: usize, period: &dyn Fn(&NaiveDateTime) -> (i32, u32)| {
+            let mut periods = HashSet::new();
+            for time in times {
+                if periods.len() >= count && !periods.contains(&period(time)) {
+                    break;
+                }
+                if periods.insert(period(time)) {
+                    keep.insert(*time);
+                }
+            }
+        };
+        keep_newest_per(self.daily, &|time| (time.year(), time.ordinal()));
+        keep_newest_per(self.weekly, &|time| {
+            (time.iso_week().year(), time.iso_week().week())
+        });
+
+        keep
+    }
+}
+
+/// Deletes the archives of `backup_dir` that fall outside of `retention`
+fn prune(backup_dir: &Path, retention: Retention) -> io::Result<()> {
+    let mut archives = Vec::new();
+    for entry in fs::read_dir(backup_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let time = name
+            .strip_prefix(ARCHIVE_PREFIX)
+            .and_then(|name| name.strip_suffix(ARCHIVE_SUFFIX))
+            .and_then(|time| NaiveDateTime::parse_from_str(time, ARCHIVE_TIME_FORMAT).ok());
+        if let Some(time) = time {
+            archives.push((time, entry.path()));
+        }
+    }
+    archives.sort_by_key(|(time, _)| std::cmp::Reverse(*time));
+
+    let times: Vec<_> = archives.iter().map(|(time, _)| *time).collect();
+    let keep = retention.keep(&times);
+
+    for (time, path) in archives {
+        if !keep.contains(&time) {
+            println!("\x1b[38;5;14mDeleting old backup {}\x1b[0m", path.display());
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retention_test() {
+        let time = |day, hour| {
+            chrono::NaiveDate::from_ymd_opt(2023, 1, day)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap()
+        };
+        // Newest first. January 2nd 2023 is a monday
+        let times = [
+            time(10, 20),
+            time(10, 8),
+            time(9, 12),
+            time(8, 12),
+            time(6, 12),
+            time(1, 12),
+        ];
+
+        let keep = |last, daily, weekly| {
+            let mut keep: Vec<_> = Retention {
+                last,
+                daily,
+                weekly,
+            }
+            .keep(&times)
+            .into_iter()
+            .collect();
+            keep.sort_by(|a, b| b.cmp(a));
+            keep
+        };
+
+        assert_eq!(vec![time(10, 20), time(10, 8)], keep(2, 0, 0));
+        assert_eq!(vec![time(10, 20), time(9, 12), time(8, 12)], keep(1, 3, 0));
+        assert_eq!(vec![time(10, 20), time(8, 12), time(1, 12)], keep(0, 0, 3));
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    hash::{BuildHasher, RandomState},
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use tokio::{
+    net::UdpSocket,
+    sync::{mpsc, Mutex},
+    task::{self, JoinHandle, JoinSet},
+};
+
+/// Sent after the packet id of offline RakNet messages
+const MAGIC: [u8; 16] = [
+    0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78,
+];
+const UNCONNECTED_PING: u8 = 0x01;
+const UNCONNECTED_PING_OPEN_CONNECTIONS: u8 = 0x02;
+const UNCONNECTED_PONG: u8 = 0x1C;
+const OPEN_CONNECTION_REQUEST_1: u8 = 0x05;
+/// Clients retry connecting several times with smaller packets, only the first attempt wakes the server up
+const WAKE_DEBOUNCE: Duration = Duration::from_secs(5);
+
+const PROTOCOL: u32 = 594;
+const VERSION: &str = "1.20.10";
+
+/// Bedrock edition players, e.g. joining through Geyser
+#[derive(Args, Debug)]
+pub struct BedrockArgs {
+    /// answer bedrock clients on this UDP port while the minecraft server sleeps (e.g. 19132 for Geyser),
+    /// and start it when one of them tries to connect.
+    /// Their names aren't known at that point, so they can't wake the server up when --whitelist is set
+    #[arg(long, value_name = "PORT")]
+    bedrock_port: Option<u16>,
+}
+
+impl BedrockArgs {
+    pub fn port(&self) -> Option<u16> {
+        self.bedrock_port
+    }
+}
+
+/// What bedrock clients are shown in their server list
+pub struct Motd {
+    pub title: String,
+    pub subtitle: String,
+    pub max_players: u64,
+    pub port: u16,
+}
+
+impl Motd {
+    fn advertisement(&self, guid: u64) -> String {
+        // Semicolons would start a new field
+        let clean = |text: &str| text.replace(';', ",");
+        format!(
+            "MCPE;{};{PROTOCOL};{VERSION};0;{};{guid};{};Survival;1;{};{};",
+            clean(&self.title),
+            self.max_players,
+            clean(&self.subtitle),
+            self.port,
+            self.port,
+        )
+    }
+}
+
+/// Builds the answer to an unconnected ping, or returns None for other packets
+fn pong(packet: &[u8], guid: u64, motd: &Motd) -> Option<Vec<u8>> {
+    let (&id, packet) = packet.split_first()?;
+    if id != UNCONNECTED_PING && id != UNCONNECTED_PING_OPEN_CONNECTIONS {
+        return None;
+    }
+    let time = packet.get(..8)?;
+    if packet.get(8..24)? != MAGIC {
+        return None;
+    }
+
+    let advertisement = motd.advertisement(guid);
+    let mut response = vec![UNCONNECTED_PONG];
+    response.extend_from_slice(time);
+    response.extend_from_slice(&guid.to_be_bytes());
+    response.extend_from_slice(&MAGIC);
+    response.extend_from_slice(&(advertisement.len() as u16).to_be_bytes());
+    response.extend_from_slice(advertisement.as_bytes());
+    Some(response)
+}
+
+fn is_connection_request(packet: &[u8]) -> bool {
+    packet.first() == Some(&OPEN_CONNECTION_REQUEST_1) && packet.get(1..17) == Some(&MAGIC[..])
+}
+
+/// Answers pings on every socket until the returned task is aborted.
+/// The addresses of clients trying to connect are sent to `wake_requests`, they aren't answered.
+pub fn spawn_responder(
+    sockets: Vec<UdpSocket>,
+    motd: Motd,
+    wake_requests: mpsc::Sender<SocketAddr>,
+) -> JoinHandle<()> {
+    task::spawn(async move {
+        let guid = RandomState::new().hash_one(std::process::id());
+        let motd = Arc::new(motd);
+        let last_wake: Arc<Mutex<Option<Instant>>> = Arc::default();
+        // Aborting the responder drops the set, which aborts every socket's task
+        let mut tasks = JoinSet::new();
+        for socket in sockets {
+            let motd = motd.clone();
+            let last_wake = last_wake.clone();
+            let wake_requests = wake_requests.clone();
+            tasks.spawn(async move {
+                let mut buffer = [0; 1500];
+                loop {
+                    let (length, address) = match socket.recv_from(&mut buffer).await {
+                        Ok(received) => received,
+                        Err(err) => {
+                            println!("\x1b[38;5;11mWarning: Couldn't receive a bedrock packet. Got err: {err}\x1b[0m");
+                            continue;
+                        }
+                    };
+                    let address = SocketAddr::new(address.ip().to_canonical(), address.port());
+                    let packet = &buffer[..length];
+
+                    if let Some(response) = pong(packet, guid, &motd) {
+                        if let Err(err) = socket.send_to(&response, address).await {
+                            println!("\x1b[38;5;11mWarning: Couldn't answer the bedrock ping from {address}. Got err: {err}\x1b[0m");
+                        }
+                    } else if is_connection_request(packet) {
+                        let mut last_wake = last_wake.lock().await;
+                        if last_wake.is_none_or(|last_wake| last_wake.elapsed() > WAKE_DEBOUNCE) {
+                            *last_wake = Some(Instant::now());
+                            // The receiver is gone once the server is starting
+                            let _ = wake_requests.send(address).await;
+                        }
+                    }
+                }
+            });
+        }
+        while tasks.join_next().await.is_some() {}
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pong_test() {
+        let motd = Motd {
+            title: "Sleeping; for now".to_owned(),
+            subtitle: "Join to start it".to_owned(),
+            max_players: 20,
+            port: 19132,
+        };
+        let mut ping = vec![UNCONNECTED_PING];
+        ping.extend_from_slice(&42_u64.to_be_bytes());
+        ping.extend_from_slice(&MAGIC);
+        ping.extend_from_slice(&7_u64.to_be_bytes());
+
+        let response = pong(&ping, 1234, &motd).unwrap();
+        assert_eq!(UNCONNECTED_PONG, response[0]);
+        assert_eq!(42_u64.to_be_bytes(), response[1..9]);
+        assert_eq!(1234_u64.to_be_bytes(), response[9..17]);
+        assert_eq!(MAGIC, response[17..33]);
+        let advertisement = String::from_utf8(response[35..].to_vec()).unwrap();
+        assert_eq!(
+            usize::from(u16::from_be_bytes([response[33], response[34]])),
+            advertisement.len()
+        );
+        assert_eq!(
+            "MCPE;Sleeping, for now;594;1.20.10;0;20;1234;Join to start it;Survival;1;19132;19132;",
+            advertisement
+        );
+
+        let mut request = vec![OPEN_CONNECTION_REQUEST_1];
+        request.extend_from_slice(&MAGIC);
+        request.push(11);
+        assert_eq!(None, pong(&request, 1234, &motd));
+        assert!(is_connection_request(&request));
+    }
+}
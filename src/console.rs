@@ -0,0 +1,338 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, Permissions},
+    io::{BufRead, Write},
+    num::NonZeroUsize,
+    os::{
+        fd::{AsFd, OwnedFd},
+        unix::fs::PermissionsExt,
+    },
+    path::{Path, PathBuf},
+    sync::{mpsc as std_mpsc, Arc, Mutex, OnceLock},
+    thread,
+    time::Duration,
+};
+
+use nix::{
+    errno::Errno,
+    sys::stat::Mode,
+    unistd::{dup2_stderr, dup2_stdout, mkfifo},
+};
+use tokio::{
+    io::{self, AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{unix::pipe, UnixListener, UnixStream},
+    sync::{broadcast, mpsc},
+    task,
+};
+
+/// Forwards the lines typed in the terminal. Stops at the end of stdin,
+/// which is reached right away when running without a terminal (e.g. under systemd or in docker)
+pub fn spawn_stdin(sender: mpsc::Sender<String>) {
+    task::spawn(forward_stdin(BufReader::new(tokio::io::stdin()), sender));
+}
+
+async fn forward_stdin<R>(mut stdin_reader: R, sender: mpsc::Sender<String>)
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line_buffer = String::new();
+    loop {
+        match stdin_reader.read_line(&mut line_buffer).await {
+            Ok(0) => {
+                println!("\x1b[38;5;14mReached the end of stdin, console commands won't be read from it anymore\x1b[0m");
+                break;
+            }
+            Ok(_) => {}
+            Err(err) => {
+                println!("\x1b[38;5;11mWarning: Couldn't read from stdin, console commands won't be read from it anymore. Got err: {err}\x1b[0m");
+                break;
+            }
+        }
+        if sender.send(line_buffer.clone()).await.is_err() || &line_buffer == "stop\n" {
+            break;
+        };
+        line_buffer.clear();
+    }
+}
+
+/// Forwards the lines written to the named pipe at `path`, which is created if it doesn't exist
+pub fn spawn_pipe(path: PathBuf, sender: mpsc::Sender<String>) -> io::Result<()> {
+    let receiver = open_pipe(&path)?;
+
+    task::spawn(async move {
+        let mut lines = BufReader::new(receiver).lines();
+        loop {
+            match lines.next_line().await {
+                Ok(Some(line)) => {
+                    if sender.send(format!("{line}\n")).await.is_err() {
+                        break;
+                    }
+                }
+                // We hold the pipe open for writing ourselves, so this shouldn't happen
+                Ok(None) => break,
+                Err(err) => {
+                    println!("\x1b[38;5;11mWarning: Couldn't read from {}, console commands won't be read from it anymore. Got err: {err}\x1b[0m", path.display());
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn open_pipe(path: &Path) -> io::Result<pipe::Receiver> {
+    match mkfifo(path, Mode::S_IRUSR | Mode::S_IWUSR) {
+        Ok(()) | Err(Errno::EEXIST) => {}
+        Err(err) => return Err(err.into()),
+    }
+    // Opening it for writing too keeps it from reaching its end every time a writer closes it
+    pipe::OpenOptions::new()
+        .read_write(true)
+        .open_receiver(path)
+}
+
+/// Set while output is captured: the real stdout, and a channel that closes once the output thread is done
+static CAPTURE: OnceLock<(OwnedFd, Mutex<std_mpsc::Receiver<()>>)> = OnceLock::new();
+
+/// Exits the process, after passing on the output that is still in the pipe when it is captured
+pub async fn exit(code: i32) -> ! {
+    if let Some((terminal, done)) = CAPTURE.get() {
+        let _ = std::io::stdout().flush();
+        // Closes our ends of the pipe, so the output thread reaches its end
+        if dup2_stdout(terminal)
+            .and_then(|_| dup2_stderr(terminal))
+            .is_ok()
+        {
+            if let Ok(done) = done.lock() {
+                let _ = done.recv_timeout(Duration::from_secs(1));
+            }
+        }
+        // Lets attached consoles write the last lines
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    std::process::exit(code)
+}
+
+/// Everything printed by the manager and the minecraft server, kept for remote consoles
+pub struct Output {
+    scrollback: Mutex<VecDeque<String>>,
+    capacity: usize,
+    live: broadcast::Sender<String>,
+}
+
+impl Output {
+    fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            scrollback: Mutex::new(VecDeque::with_capacity(capacity.get())),
+            capacity: capacity.get(),
+            live: broadcast::channel(capacity.get()).0,
+        }
+    }
+
+    fn push(&self, line: String) {
+        let mut scrollback = self.lock();
+        if scrollback.len() >= self.capacity {
+            scrollback.pop_front();
+        }
+        scrollback.push_back(line.clone());
+        // Nobody being attached isn't an error
+        let _ = self.live.send(line);
+    }
+
+    /// Returns the scrollback and the lines printed after it
+    fn subscribe(&self) -> (Vec<String>, broadcast::Receiver<String>) {
+        // Subscribing while holding the lock ensures no line is missed or sent twice
+        let scrollback = self.lock();
+        (scrollback.iter().cloned().collect(), self.live.subscribe())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<String>> {
+        self.scrollback
+            .lock()
+            .expect("no thread should panic while holding the scrollback lock")
+    }
+}
+
+/// Sends stdout and stderr, which the minecraft server inherits, through a pipe.
+/// Everything written to them still reaches the terminal, and the last `capacity` lines are kept.
+pub fn capture_output(capacity: NonZeroUsize) -> io::Result<Arc<Output>> {
+    let terminal = std::io::stdout().as_fd().try_clone_to_owned()?;
+    let (done_sender, done) = std_mpsc::channel::<()>();
+    let _ = CAPTURE.set((terminal.try_clone()?, Mutex::new(done)));
+    let (reader, writer) = std::io::pipe()?;
+    dup2_stdout(&writer)?;
+    dup2_stderr(&writer)?;
+    drop(writer);
+
+    let output = Arc::new(Output::new(capacity));
+
+    let captured = output.clone();
+    thread::spawn(move || {
+        let _done_sender = done_sender;
+        let mut terminal = File::from(terminal);
+        for line in std::io::BufReader::new(reader).split(b'\n') {
+            let Ok(line) = line else { break };
+            // The terminal may be gone when running without one, remote consoles still want the output
+            let _ = terminal
+                .write_all(&line)
+                .and_then(|_| terminal.write_all(b"\n"));
+            captured.push(String::from_utf8_lossy(&line).into_owned());
+        }
+    });
+
+    Ok(output)
+}
+
+/// Lets clients attach to the console through the unix socket at `path`.
+/// They get the scrollback, then live output, and their lines are handled like the ones typed in the terminal.
+pub fn spawn_socket(
+    path: PathBuf,
+    output: Arc<Output>,
+    sender: mpsc::Sender<String>,
+) -> io::Result<()> {
+    // A socket left behind by a previous run would prevent binding, but a live one must not be stolen
+    if path.exists() && std::os::unix::net::UnixStream::connect(&path).is_err() {
+        fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    // Attached clients control the minecraft server
+    fs::set_permissions(&path, Permissions::from_mode(0o600))?;
+
+    task::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    task::spawn(serve(stream, output.clone(), sender.clone()));
+                }
+                Err(err) => {
+                    println!("\x1b[38;5;11mWarning: Couldn't accept a console client. Got err: {err}\x1b[0m");
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn serve(stream: UnixStream, output: Arc<Output>, sender: mpsc::Sender<String>) {
+    let (read_half, mut write_half) = stream.into_split();
+    let (scrollback, mut live) = output.subscribe();
+
+    let writer = task::spawn(async move {
+        for line in scrollback {
+            write_half.write_all(format!("{line}\n").as_bytes()).await?;
+        }
+        loop {
+            let line = match live.recv().await {
+                Ok(line) => line,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    format!("\x1b[38;5;11m[{missed} lines were skipped]\x1b[0m")
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            write_half.write_all(format!("{line}\n").as_bytes()).await?;
+        }
+        io::Result::Ok(())
+    });
+
+    let mut lines = BufReader::new(read_half).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if sender.send(format!("{line}\n")).await.is_err() {
+            break;
+        }
+    }
+    writer.abort();
+}
+
+/// Connects the terminal to the console of the activity manager listening on `path`
+pub async fn attach(path: &Path) -> io::Result<()> {
+    let stream = UnixStream::connect(path).await?;
+    let (mut read_half, mut write_half) = stream.into_split();
+    let (mut stdin, mut stdout) = (io::stdin(), io::stdout());
+
+    tokio::select!(
+        result = io::copy(&mut read_half, &mut stdout) => result.map(drop),
+        result = io::copy(&mut stdin, &mut write_half) => result.map(drop),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Everything forwarded until the sender is dropped
+    async fn received(mut receiver: mpsc::Receiver<String>) -> Vec<String> {
+        let mut lines = Vec::new();
+        while let Some(line) = receiver.recv().await {
+            lines.push(line);
+        }
+        lines
+    }
+
+    #[tokio::test]
+    async fn forward_stdin_test() {
+        let (sender, receiver) = mpsc::channel(8);
+        forward_stdin(&b"list\nsay hi"[..], sender).await;
+        assert_eq!(vec!["list\n", "say hi"], received(receiver).await);
+
+        // Nothing is read after stop, as we are exiting
+        let (sender, receiver) = mpsc::channel(8);
+        forward_stdin(&b"stop\nstart\n"[..], sender).await;
+        assert_eq!(vec!["stop\n"], received(receiver).await);
+
+        // Without a terminal
+        let (sender, receiver) = mpsc::channel(8);
+        forward_stdin(&b""[..], sender).await;
+        assert!(received(receiver).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn pipe_test() {
+        let dir =
+            std::env::temp_dir().join(format!("activitymanager-pipe-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("console");
+        let (sender, mut receiver) = mpsc::channel(8);
+        spawn_pipe(path.clone(), sender).unwrap();
+
+        // Each writer closes the pipe after its command, like `echo start > PATH`
+        for command in ["start", "stop"] {
+            let mut writer = fs::OpenOptions::new().write(true).open(&path).unwrap();
+            writeln!(writer, "{command}").unwrap();
+            drop(writer);
+            assert_eq!(Some(format!("{command}\n")), receiver.recv().await);
+        }
+
+        // An existing pipe is reused
+        assert!(open_pipe(&path).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn output_test() {
+        let output = Output::new(NonZeroUsize::new(2).unwrap());
+        for line in ["first", "second", "third"] {
+            output.push(line.to_owned());
+        }
+        // Only the last lines are kept
+        let (scrollback, mut live) = output.subscribe();
+        assert_eq!(vec!["second", "third"], scrollback);
+
+        // Lines printed after subscribing only come live
+        output.push("fourth".to_owned());
+        assert_eq!("fourth", live.recv().await.unwrap());
+        assert_eq!(vec!["third", "fourth"], output.subscribe().0);
+
+        // Clients falling behind by more than the scrollback are told what they missed
+        for line in ["fifth", "sixth", "seventh"] {
+            output.push(line.to_owned());
+        }
+        assert!(matches!(
+            live.recv().await,
+            Err(broadcast::error::RecvError::Lagged(1))
+        ));
+        assert_eq!("sixth", live.recv().await.unwrap());
+        assert_eq!("seventh", live.recv().await.unwrap());
+    }
+}
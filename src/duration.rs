@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+/// Parses durations like `90s`, `15m`, `2h` or `1d`. A bare number is a number of seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("'{value}' should start with a whole number"))?;
+
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        other => {
+            return Err(format!(
+                "unknown unit '{other}' in '{value}', expected one of s, m, h or d"
+            ))
+        }
+    };
+
+    number
+        .checked_mul(multiplier)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("'{value}' is too long"))
+}
+
+/// Like `parse_duration`, for intervals that can't be zero
+pub fn parse_nonzero_duration(value: &str) -> Result<Duration, String> {
+    match parse_duration(value)? {
+        Duration::ZERO => Err(format!("'{value}' should be longer than zero")),
+        duration => Ok(duration),
+    }
+}
+
+/// Like `parse_duration`, but bare numbers are minutes, as timeouts used to be given in whole minutes
+pub fn parse_timeout(value: &str) -> Result<Duration, String> {
+    match value.trim().parse::<u64>() {
+        Ok(minutes) => minutes
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or_else(|| format!("'{value}' is too long")),
+        Err(_) => parse_duration(value),
+    }
+}
+
+/// The inverse of `parse_duration`, using the largest unit that divides the duration
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    for (unit, size) in [("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60)] {
+        if seconds != 0 && seconds.is_multiple_of(size) {
+            return format!("{}{unit}", seconds / size);
+        }
+    }
+    format!("{seconds}s")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_test() {
+        assert_eq!(Duration::from_secs(90), parse_duration("90s").unwrap());
+        assert_eq!(Duration::from_secs(90), parse_duration("90").unwrap());
+        assert_eq!(Duration::from_secs(15 * 60), parse_duration("15m").unwrap());
+        assert_eq!(Duration::from_secs(2 * 3600), parse_duration("2h").unwrap());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("15 minutes").is_err());
+        assert!(parse_duration("-5s").is_err());
+        assert_eq!(Duration::from_secs(5 * 60), parse_timeout("5").unwrap());
+        assert_eq!(Duration::from_secs(5), parse_timeout("5s").unwrap());
+        assert!(parse_duration("99999999999999999d").is_err());
+        assert!(parse_timeout("999999999999999999").is_err());
+    }
+
+    #[test]
+    fn parse_nonzero_duration_test() {
+        assert_eq!(
+            Duration::from_secs(10),
+            parse_nonzero_duration("10s").unwrap()
+        );
+        assert!(parse_nonzero_duration("0").is_err());
+        assert!(parse_nonzero_duration("0m").is_err());
+    }
+
+    #[test]
+    fn format_duration_test() {
+        assert_eq!("90s", format_duration(Duration::from_secs(90)));
+        assert_eq!("15m", format_duration(Duration::from_secs(15 * 60)));
+        assert_eq!("0s", format_duration(Duration::ZERO));
+    }
+}
@@ -0,0 +1,248 @@
+use std::{process::Stdio, sync::Arc, time::Duration};
+
+use clap::{Args, ValueEnum};
+use tokio::{process::Command, task};
+
+use crate::{duration::parse_duration, journal::Event};
+
+/// Points in the lifecycle of the minecraft server where user commands can be run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Hook {
+    /// Before the server is started. Can abort the start
+    PreStart,
+    /// When the server first answers a status request
+    PostReady,
+    /// Before the server is asked to stop
+    PreStop,
+    /// After the server exited, when it was asked to or exited cleanly
+    PostStop,
+    /// After the server exited with a failure status without being asked to
+    Crash,
+    /// When a player was refused a wake-up
+    WakeRequestRejected,
+}
+
+impl std::fmt::Display for Hook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.to_possible_value()
+                .expect("no hook is skipped")
+                .get_name()
+        )
+    }
+}
+
+fn parse_hook(value: &str) -> Result<(Hook, String), String> {
+    let (hook, command) = value
+        .split_once('=')
+        .ok_or_else(|| format!("'{value}' should look like '<hook>=<command>'"))?;
+    Ok((Hook::from_str(hook, true)?, command.to_owned()))
+}
+
+/// Commands run around the lifecycle of the minecraft server.
+///
+/// Commands are run with `/bin/sh -c` and get the details of the event in environment variables:
+/// `AM_HOOK`, `AM_EVENT` and one `AM_<FIELD>` per field of the journal entry (e.g. `AM_PLAYER`).
+#[derive(Args, Debug)]
+pub struct Hooks {
+    /// run a command with /bin/sh on a lifecycle event (can be repeated). Hooks are pre-start, post-ready,
+    /// pre-stop, post-stop, crash and wake-request-rejected. Details of the event are passed as AM_HOOK, AM_EVENT,
+    /// AM_PLAYER, AM_REASON, AM_STATUS... environment variables
+    #[arg(long = "hook", value_name = "HOOK=COMMAND", value_parser = parse_hook)]
+    hooks: Vec<(Hook, String)>,
+
+    /// hooks running for longer than this are killed
+    #[arg(long, value_name = "DURATION", default_value = "30s", value_parser = parse_duration)]
+    hook_timeout: Duration,
+
+    /// if set, the minecraft server isn't started when a pre-start hook fails
+    #[arg(long)]
+    abort_on_pre_start_failure: bool,
+}
+
+impl Hooks {
+    /// Runs every command registered for `hook` one after the other and returns whether they all succeeded
+    pub async fn run(&self, hook: Hook, event: &Event, extra_env: &[(&str, String)]) -> bool {
+        let mut success = true;
+
+        for (_, command) in self.hooks.iter().filter(|(other, _)| *other == hook) {
+            println!("\x1b[38;5;14mRunning {hook} hook: {command}\x1b[0m");
+
+            let mut child = match Command::new("/bin/sh")
+                .args(["-c", command])
+                .envs(event_env(hook, event))
+                .envs(extra_env.iter().map(|(key, value)| (*key, value.as_str())))
+                .stdin(Stdio::null())
+                .kill_on_drop(true)
+                .spawn()
+            {
+                Ok(child) => child,
+                Err(err) => {
+                    println!(
+                        "\x1b[38;5;11mWarning: Couldn't run {hook} hook. Got err: {err}\x1b[0m"
+                    );
+                    success = false;
+                    continue;
+                }
+            };
+
+            match tokio::time::timeout(self.hook_timeout, child.wait()).await {
+                Ok(Ok(status)) if status.success() => {}
+                Ok(Ok(status)) => {
+                    println!("\x1b[38;5;11mWarning: {hook} hook failed on status: {status}\x1b[0m");
+                    success = false;
+                }
+                Ok(Err(err)) => {
+                    println!("\x1b[38;5;11mWarning: Couldn't wait for {hook} hook. Got err: {err}\x1b[0m");
+                    success = false;
+                }
+                Err(_) => {
+                    println!("\x1b[38;5;11mWarning: {hook} hook timed out and was killed\x1b[0m");
+                    success = false;
+                }
+            }
+        }
+
+        success
+    }
+
+    /// Like `run`, but in the background
+    pub fn spawn(self: &Arc<Self>, hook: Hook, event: Event) {
+        if self.hooks.iter().any(|(other, _)| *other == hook) {
+            let hooks = self.clone();
+            task::spawn(async move { hooks.run(hook, &event, &[]).await });
+        }
+    }
+
+    /// Runs the pre-start hooks and returns whether the server should be started
+    pub async fn pre_start(&self, event: &Event) -> bool {
+        let success = self.run(Hook::PreStart, event, &[]).await;
+        if !success && self.abort_on_pre_start_failure {
+            println!(
+                "\x1b[38;5;11mA pre-start hook failed, not starting the minecraft server\x1b[0m"
+            );
+            return false;
+        }
+        true
+    }
+}
+
+/// Turns the fields of the journal entry for `event` into environment variables
+fn event_env(hook: Hook, event: &Event) -> Vec<(String, String)> {
+    let mut env = vec![("AM_HOOK".to_owned(), hook.to_string())];
+
+    if let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(event) {
+        env.extend(fields.into_iter().map(|(key, value)| {
+            (
+                format!("AM_{}", key.to_uppercase().replace('-', "_")),
+                match value {
+                    serde_json::Value::String(string) => string,
+                    serde_json::Value::Null => String::new(),
+                    other => other.to_string(),
+                },
+            )
+        }));
+    }
+
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hooks(hooks: &[(Hook, &str)], abort_on_pre_start_failure: bool) -> Hooks {
+        Hooks {
+            hooks: hooks
+                .iter()
+                .map(|(hook, command)| (*hook, (*command).to_owned()))
+                .collect(),
+            hook_timeout: Duration::from_millis(500),
+            abort_on_pre_start_failure,
+        }
+    }
+
+    #[test]
+    fn event_env_test() {
+        let event = Event::StartRequested {
+            player: "Notch".to_owned(),
+            uuid: None,
+            address: "127.0.0.1:54321".to_owned(),
+        };
+        let mut env = event_env(Hook::PreStart, &event);
+        env.sort();
+        let env: Vec<_> = env
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("AM_ADDRESS", "127.0.0.1:54321"),
+                ("AM_EVENT", "start-requested"),
+                ("AM_HOOK", "pre-start"),
+                ("AM_PLAYER", "Notch"),
+                ("AM_UUID", ""),
+            ],
+            env
+        );
+    }
+
+    #[tokio::test]
+    async fn run_test() {
+        let event = Event::ConsoleStart;
+        let hooks = hooks(
+            &[
+                (
+                    Hook::PreStart,
+                    "test \"$AM_HOOK $AM_EVENT $AM_STATUS\" = 'pre-start console-start 0'",
+                ),
+                (Hook::PostStop, "true"),
+                (Hook::PostStop, "exit 1"),
+                (Hook::Crash, "sleep 5"),
+            ],
+            false,
+        );
+
+        assert!(
+            hooks
+                .run(Hook::PreStart, &event, &[("AM_STATUS", "0".to_owned())])
+                .await
+        );
+        assert!(!hooks.run(Hook::PreStart, &event, &[]).await);
+        // Every command runs, and all of them have to succeed
+        assert!(!hooks.run(Hook::PostStop, &event, &[]).await);
+        // Hooks without commands succeed
+        assert!(hooks.run(Hook::PreStop, &event, &[]).await);
+
+        let start = std::time::Instant::now();
+        assert!(!hooks.run(Hook::Crash, &event, &[]).await);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn pre_start_test() {
+        let event = Event::ConsoleStart;
+        assert!(
+            hooks(&[(Hook::PreStart, "exit 1")], false)
+                .pre_start(&event)
+                .await
+        );
+        assert!(
+            !hooks(&[(Hook::PreStart, "exit 1")], true)
+                .pre_start(&event)
+                .await
+        );
+        assert!(
+            hooks(&[(Hook::PreStart, "true")], true)
+                .pre_start(&event)
+                .await
+        );
+        assert!(
+            !hooks(&[(Hook::PreStart, "sleep 5")], true)
+                .pre_start(&event)
+                .await
+        );
+    }
+}
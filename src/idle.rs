@@ -0,0 +1,200 @@
+use std::time::{Duration, Instant};
+
+use clap::{Args, ValueEnum};
+
+use crate::duration::{format_duration, parse_duration, parse_nonzero_duration, parse_timeout};
+
+/// When the minecraft server is considered idle. Durations are written like '90s', '15m' or '2h'.
+#[derive(Args, Debug)]
+pub struct IdleArgs {
+    /// how long the server may stay empty after the last player left (bare numbers are minutes)
+    #[arg(long, short, default_value = "5m", value_parser = parse_timeout)]
+    timeout: Duration,
+
+    /// how long the server may stay up without anyone joining after it became reachable [default: the timeout]
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    boot_grace: Option<Duration>,
+
+    /// the server is never stopped for inactivity before it has been running for this long
+    #[arg(long, value_name = "DURATION", default_value = "0s", value_parser = parse_duration)]
+    min_uptime: Duration,
+
+    /// how often the player count is queried from the minecraft server
+    #[arg(long, value_name = "DURATION", default_value = "10s", value_parser = parse_nonzero_duration)]
+    probe_interval: Duration,
+
+    /// what to do with an idle server. Freezing requires the minecraft server to listen on --probe-address,
+    /// as activity manager keeps its port to answer players while the server is frozen.
+    /// Players are then forwarded to it from activity manager's address, unless --send-proxy-protocol is set
+    #[arg(long, value_enum, default_value_t = IdleAction::Stop, requires_if("freeze", "probe_address"))]
+    idle_action: IdleAction,
+}
+
+impl IdleArgs {
+    pub fn probe_interval(&self) -> Duration {
+        self.probe_interval
+    }
+
+    pub fn action(&self) -> IdleAction {
+        self.idle_action
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum IdleAction {
+    /// Stop the minecraft server
+    Stop,
+    /// Suspend the minecraft server, to resume it within a second when a player connects
+    Freeze,
+}
+
+/// Which idle policy decided to stop the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleReason {
+    /// Nobody joined after the server woke up
+    NoJoin,
+    /// The last player left
+    Empty,
+}
+
+impl std::fmt::Display for IdleReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::NoJoin => "no-join",
+                Self::Empty => "empty",
+            }
+        )
+    }
+}
+
+/// Follows the player count of a running server to decide when it has been idle for too long
+pub struct IdleTracker {
+    started: Instant,
+    /// When the server first answered a status request
+    ready: Option<Instant>,
+    /// When players were last seen online
+    last_player: Option<Instant>,
+}
+
+impl IdleTracker {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            started: now,
+            ready: None,
+            last_player: None,
+        }
+    }
+
+    /// Returns whether this is the first time the server answered
+    pub fn observe(&mut self, playercount: u64, now: Instant) -> bool {
+        let first = self.ready.is_none();
+        self.ready.get_or_insert(now);
+        if playercount != 0 {
+            self.last_player = Some(now);
+        }
+        first
+    }
+
+    /// Returns why the server should be stopped and the duration it was idle for, if it should be.
+    /// `timeout` overrides the post-leave timeout of `args`, like the schedule does.
+    pub fn should_stop(
+        &self,
+        args: &IdleArgs,
+        timeout: Option<Duration>,
+        playercount: u64,
+        now: Instant,
+    ) -> Option<(IdleReason, Duration)> {
+        if playercount != 0 || now.duration_since(self.started) < args.min_uptime {
+            return None;
+        }
+
+        let timeout = timeout.unwrap_or(args.timeout);
+
+        let (reason, since, limit) = match self.last_player {
+            Some(last_player) => (IdleReason::Empty, last_player, timeout),
+            None => (
+                IdleReason::NoJoin,
+                self.ready.unwrap_or(now),
+                args.boot_grace.unwrap_or(timeout),
+            ),
+        };
+
+        let idle = now.duration_since(since);
+        (idle >= limit).then_some((reason, idle))
+    }
+}
+
+/// Describes a decision of `IdleTracker::should_stop` for humans
+pub fn describe(reason: IdleReason, idle: Duration) -> String {
+    let idle = format_duration(Duration::from_secs(idle.as_secs()));
+    match reason {
+        IdleReason::NoJoin => format!("nobody joined in the {idle} since it became reachable"),
+        IdleReason::Empty => format!("it has been empty for {idle}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(timeout: u64, boot_grace: Option<u64>, min_uptime: u64) -> IdleArgs {
+        IdleArgs {
+            timeout: Duration::from_secs(timeout),
+            boot_grace: boot_grace.map(Duration::from_secs),
+            min_uptime: Duration::from_secs(min_uptime),
+            probe_interval: Duration::from_secs(10),
+            idle_action: IdleAction::Stop,
+        }
+    }
+
+    #[test]
+    fn boot_grace_test() {
+        let start = Instant::now();
+        let args = args(60, Some(300), 0);
+        let mut tracker = IdleTracker::new(start);
+
+        // The grace period starts when the server becomes reachable
+        tracker.observe(0, start + Duration::from_secs(200));
+        let at = |seconds| start + Duration::from_secs(seconds);
+        assert_eq!(None, tracker.should_stop(&args, None, 0, at(400)));
+        assert_eq!(
+            Some((IdleReason::NoJoin, Duration::from_secs(300))),
+            tracker.should_stop(&args, None, 0, at(500))
+        );
+    }
+
+    #[test]
+    fn empty_timeout_test() {
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+        let args = args(60, Some(300), 0);
+        let mut tracker = IdleTracker::new(start);
+
+        tracker.observe(2, at(10));
+        assert_eq!(None, tracker.should_stop(&args, None, 2, at(100)));
+        assert_eq!(None, tracker.should_stop(&args, None, 0, at(50)));
+        assert_eq!(
+            Some((IdleReason::Empty, Duration::from_secs(60))),
+            tracker.should_stop(&args, None, 0, at(70))
+        );
+        assert_eq!(
+            None,
+            tracker.should_stop(&args, Some(Duration::from_secs(120)), 0, at(70))
+        );
+    }
+
+    #[test]
+    fn min_uptime_test() {
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+        let args = args(60, None, 600);
+        let mut tracker = IdleTracker::new(start);
+
+        tracker.observe(0, at(0));
+        assert_eq!(None, tracker.should_stop(&args, None, 0, at(300)));
+        assert!(tracker.should_stop(&args, None, 0, at(600)).is_some());
+    }
+}
@@ -0,0 +1,416 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader},
+};
+
+/// Something that happened to the minecraft server, as recorded in the journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    /// A player tried to log in while the server was asleep
+    StartRequested {
+        player: String,
+        uuid: Option<String>,
+        address: String,
+    },
+    /// A bedrock client tried to connect while the server was asleep. Its player isn't known yet
+    BedrockStartRequested { address: String },
+    /// A player was refused a wake-up (ban, whitelist, schedule or limits)
+    WakeRejected {
+        player: String,
+        uuid: Option<String>,
+        address: String,
+        reason: String,
+    },
+    /// A bedrock client was refused a wake-up (ban, whitelist, schedule or limits)
+    BedrockWakeRejected { address: String, reason: String },
+    /// A start was requested, but a pre-start hook aborted it
+    StartAborted { request: String, reason: String },
+    /// Someone typed `start` in the console
+    ConsoleStart,
+    /// The server was started because of a `--force-on` window
+    ScheduledStart { window: String },
+    /// The server answered its first status request after being started
+    Ready,
+    /// The server was stopped because nobody was online
+    IdleStop { reason: String, idle: String },
+    /// The server was suspended because nobody was online
+    IdleFreeze { reason: String, idle: String },
+    /// Someone typed `stop` or `spoof` in the console
+    ManualStop { command: String },
+    /// The server exited with a failure status without being asked to
+    Crash { status: String },
+    /// The server exited successfully without being asked to (e.g. `/stop` in game)
+    Exit { status: String },
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::StartRequested { .. } => EventKind::StartRequested,
+            Self::BedrockStartRequested { .. } => EventKind::BedrockStartRequested,
+            Self::WakeRejected { .. } => EventKind::WakeRejected,
+            Self::BedrockWakeRejected { .. } => EventKind::BedrockWakeRejected,
+            Self::StartAborted { .. } => EventKind::StartAborted,
+            Self::ConsoleStart => EventKind::ConsoleStart,
+            Self::ScheduledStart { .. } => EventKind::ScheduledStart,
+            Self::Ready => EventKind::Ready,
+            Self::IdleStop { .. } => EventKind::IdleStop,
+            Self::IdleFreeze { .. } => EventKind::IdleFreeze,
+            Self::ManualStop { .. } => EventKind::ManualStop,
+            Self::Crash { .. } => EventKind::Crash,
+            Self::Exit { .. } => EventKind::Exit,
+        }
+    }
+
+    fn player(&self) -> Option<&str> {
+        match self {
+            Self::StartRequested { player, .. } | Self::WakeRejected { player, .. } => Some(player),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::StartRequested {
+                player,
+                uuid,
+                address,
+            } => {
+                write!(f, "start requested by {player}")?;
+                if let Some(uuid) = uuid {
+                    write!(f, " ({uuid})")?;
+                }
+                write!(f, " from {address}")
+            }
+            Self::BedrockStartRequested { address } => {
+                write!(f, "start requested by a bedrock client from {address}")
+            }
+            Self::WakeRejected {
+                player,
+                uuid,
+                address,
+                reason,
+            } => {
+                write!(f, "wake-up refused to {player}")?;
+                if let Some(uuid) = uuid {
+                    write!(f, " ({uuid})")?;
+                }
+                write!(f, " from {address}: {reason}")
+            }
+            Self::BedrockWakeRejected { address, reason } => {
+                write!(
+                    f,
+                    "wake-up refused to a bedrock client from {address}: {reason}"
+                )
+            }
+            Self::StartAborted { request, reason } => {
+                write!(f, "start aborted ({reason}): {request}")
+            }
+            Self::ConsoleStart => write!(f, "start requested from the console"),
+            Self::ScheduledStart { window } => write!(f, "started by the schedule ({window})"),
+            Self::Ready => write!(f, "became reachable"),
+            Self::IdleStop { reason, idle } => {
+                write!(f, "stopped after {idle} of inactivity ({reason})")
+            }
+            Self::IdleFreeze { reason, idle } => {
+                write!(f, "frozen after {idle} of inactivity ({reason})")
+            }
+            Self::ManualStop { command } => write!(f, "stopped from the console ('{command}')"),
+            Self::Crash { status } => write!(f, "crashed ({status})"),
+            Self::Exit { status } => write!(f, "exited on its own ({status})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EventKind {
+    StartRequested,
+    BedrockStartRequested,
+    WakeRejected,
+    BedrockWakeRejected,
+    StartAborted,
+    ConsoleStart,
+    ScheduledStart,
+    Ready,
+    IdleStop,
+    IdleFreeze,
+    ManualStop,
+    Crash,
+    Exit,
+}
+
+impl std::fmt::Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.to_possible_value()
+                .expect("no event kind is skipped")
+                .get_name()
+        )
+    }
+}
+
+/// A single line of the journal
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub time: DateTime<Local>,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Append-only record of the lifecycle of the minecraft server, stored as one JSON object per line
+pub struct Journal {
+    path: Option<PathBuf>,
+}
+
+impl Journal {
+    /// A journal without a path silently discards every event
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self { path }
+    }
+
+    pub async fn record(&self, event: Event) {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return,
+        };
+
+        let entry = Entry {
+            time: Local::now(),
+            event,
+        };
+
+        if let Err(err) = append_entry(path, &entry).await {
+            println!(
+                "\x1b[38;5;11mWarning: Could not write to journal {}. Got err: {err}\x1b[0m",
+                path.display()
+            );
+        }
+    }
+}
+
+async fn append_entry(path: &Path, entry: &Entry) -> io::Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    file.flush().await
+}
+
+/// List past events recorded in a journal
+#[derive(Args, Debug)]
+pub struct JournalArgs {
+    /// path to the journal file
+    path: PathBuf,
+
+    /// only show events of this kind (can be repeated)
+    #[arg(long, short, value_enum)]
+    kind: Vec<EventKind>,
+
+    /// only show events that happened at or after this date (YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS)
+    #[arg(long, value_parser = parse_date)]
+    since: Option<DateTime<Local>>,
+
+    /// only show events that happened before this date (YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS)
+    #[arg(long, value_parser = parse_date)]
+    until: Option<DateTime<Local>>,
+
+    /// only show wake-ups requested by this player
+    #[arg(long, short)]
+    player: Option<String>,
+
+    /// only show the last N matching events
+    #[arg(long, short = 'n')]
+    last: Option<usize>,
+}
+
+impl JournalArgs {
+    fn matches(&self, entry: &Entry) -> bool {
+        (self.kind.is_empty() || self.kind.contains(&entry.event.kind()))
+            && self.since.is_none_or(|since| entry.time >= since)
+            && self.until.is_none_or(|until| entry.time < until)
+            && self.player.as_ref().is_none_or(|player| {
+                entry
+                    .event
+                    .player()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(player))
+            })
+    }
+}
+
+fn parse_date(value: &str) -> Result<DateTime<Local>, String> {
+    let naive = if let Ok(date_time) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S") {
+        date_time
+    } else if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        date.and_hms_opt(0, 0, 0)
+            .expect("midnight should be a valid time")
+    } else {
+        return Err(format!(
+            "'{value}' is not a date of the form YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS"
+        ));
+    };
+
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| format!("'{value}' does not exist in the local timezone"))
+}
+
+/// Prints the events of the journal matching the filters of `args`
+pub async fn list(args: JournalArgs) -> io::Result<()> {
+    let file = fs::File::open(&args.path).await?;
+    let mut lines = BufReader::new(file).lines();
+
+    let mut entries = Vec::new();
+    let mut line_number = 0;
+    while let Some(line) = lines.next_line().await? {
+        line_number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Entry>(&line) {
+            Ok(entry) => {
+                if args.matches(&entry) {
+                    entries.push(entry);
+                }
+            }
+            Err(err) => println!(
+                "\x1b[38;5;11mWarning: skipping line {line_number} of {}: {err}\x1b[0m",
+                args.path.display()
+            ),
+        }
+    }
+
+    let skip = match args.last {
+        Some(last) => entries.len().saturating_sub(last),
+        None => 0,
+    };
+
+    for entry in entries.iter().skip(skip) {
+        println!(
+            "{} \x1b[38;5;14m{}\x1b[0m {}",
+            entry.time.format("[%Y-%m-%d %H:%M:%S]"),
+            entry.event.kind(),
+            entry.event
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(kind: Vec<EventKind>, since: Option<&str>, player: Option<&str>) -> JournalArgs {
+        JournalArgs {
+            path: PathBuf::new(),
+            kind,
+            since: since.map(|since| parse_date(since).unwrap()),
+            until: None,
+            last: None,
+            player: player.map(str::to_owned),
+        }
+    }
+
+    fn entry(time: &str, event: Event) -> Entry {
+        Entry {
+            time: parse_date(time).unwrap(),
+            event,
+        }
+    }
+
+    #[test]
+    fn parse_date_test() {
+        let midnight = parse_date("2023-01-10").unwrap();
+        assert_eq!(
+            midnight.naive_local(),
+            NaiveDate::from_ymd_opt(2023, 1, 10)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        );
+        assert_eq!(
+            midnight + chrono::Duration::seconds(8 * 3600 + 30 * 60),
+            parse_date("2023-01-10T08:30:00").unwrap()
+        );
+        assert!(parse_date("10/01/2023").is_err());
+        assert!(parse_date("2023-01-10 08:30").is_err());
+    }
+
+    #[test]
+    fn matches_test() {
+        let start = entry(
+            "2023-01-10T08:30:00",
+            Event::StartRequested {
+                player: "Notch".to_owned(),
+                uuid: None,
+                address: "127.0.0.1:54321".to_owned(),
+            },
+        );
+        let stop = entry(
+            "2023-01-10T09:30:00",
+            Event::ManualStop {
+                command: "stop".to_owned(),
+            },
+        );
+
+        let everything = args(Vec::new(), None, None);
+        assert!(everything.matches(&start) && everything.matches(&stop));
+
+        let starts = args(vec![EventKind::StartRequested], None, None);
+        assert!(starts.matches(&start) && !starts.matches(&stop));
+
+        let since = args(Vec::new(), Some("2023-01-10T09:00:00"), None);
+        assert!(!since.matches(&start) && since.matches(&stop));
+
+        // Events without a player never match a player filter
+        let notch = args(Vec::new(), None, Some("notch"));
+        assert!(notch.matches(&start) && !notch.matches(&stop));
+        assert!(!args(Vec::new(), None, Some("jeb_")).matches(&start));
+    }
+
+    #[tokio::test]
+    async fn append_entry_test() {
+        let path =
+            std::env::temp_dir().join(format!("activitymanager-journal-{}", std::process::id()));
+        let _ = fs::remove_file(&path).await;
+
+        let events = [
+            Event::ConsoleStart,
+            Event::IdleStop {
+                reason: "nobody online".to_owned(),
+                idle: "10m".to_owned(),
+            },
+        ];
+        for event in &events {
+            append_entry(&path, &entry("2023-01-10T08:30:00", event.clone()))
+                .await
+                .unwrap();
+        }
+
+        let contents = fs::read_to_string(&path).await.unwrap();
+        fs::remove_file(&path).await.unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(events.len(), lines.len());
+        for (line, event) in lines.into_iter().zip(&events) {
+            let read: Entry = serde_json::from_str(line).unwrap();
+            assert_eq!(parse_date("2023-01-10T08:30:00").unwrap(), read.time);
+            assert_eq!(event.to_string(), read.event.to_string());
+        }
+    }
+}
@@ -0,0 +1,9 @@
+//! The minecraft protocol implementation activity manager is built on:
+//! a server side codec, the data types of the protocol and the packets it knows about.
+//!
+//! Depend on it with `default-features = false` to leave out the dependencies of the activitymanager binary.
+
+// The code generated by `#[derive(Encode, Decode)]` refers to the crate by name, also from inside it
+extern crate self as activitymanager;
+
+pub mod mc_protocol;
@@ -0,0 +1,400 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::duration::{format_duration, parse_duration};
+
+/// At most `count` events per `period`, written as `<count>/<duration>` (e.g. `10/1m`)
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    count: usize,
+    period: Duration,
+}
+
+impl std::str::FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (count, period) = value
+            .split_once('/')
+            .ok_or_else(|| format!("'{value}' should look like '<count>/<duration>'"))?;
+        Ok(Self {
+            count: count
+                .parse()
+                .map_err(|_| format!("'{count}' is not a valid count"))?,
+            period: parse_duration(period)?,
+        })
+    }
+}
+
+/// Limits protecting the spoofer from floods and the server from being kept awake on purpose
+#[derive(Args, Debug)]
+pub struct LimitArgs {
+    /// maximum number of connections accepted from a single IP while spoofing, e.g. '20/1m'.
+    /// Counted before anything is read, so behind a proxy every connection comes from the proxy's IP
+    #[arg(long, value_name = "COUNT/DURATION")]
+    connection_rate: Option<RateLimit>,
+
+    /// maximum number of connections handled at the same time while spoofing
+    #[arg(long, value_name = "COUNT")]
+    max_connections: Option<usize>,
+
+    /// minimum time between two wake-ups requested by players, e.g. '10m'
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    start_cooldown: Option<Duration>,
+
+    /// maximum number of start requests from a single IP or player name, e.g. '3/1h'
+    #[arg(long, value_name = "COUNT/DURATION")]
+    start_request_rate: Option<RateLimit>,
+
+    /// kick message for players over the connection rate
+    #[arg(
+        long,
+        value_name = "MESSAGE",
+        default_value = "Too many connections, please wait a bit before trying again"
+    )]
+    connection_rate_message: String,
+
+    /// kick message for players connecting while the spoofer is at its connection cap
+    #[arg(
+        long,
+        value_name = "MESSAGE",
+        default_value = "Too many players are connecting right now, please try again"
+    )]
+    max_connections_message: String,
+
+    /// kick message for players requesting a wake-up during the start cooldown
+    #[arg(
+        long,
+        value_name = "MESSAGE",
+        default_value = "The server was started recently, it can be started again in {remaining}"
+    )]
+    start_cooldown_message: String,
+
+    /// kick message for players over the start request rate
+    #[arg(
+        long,
+        value_name = "MESSAGE",
+        default_value = "You requested too many starts recently, please wait before trying again"
+    )]
+    start_request_rate_message: String,
+}
+
+/// Why a connection or a start request was turned down, with the kick message to show
+pub struct Refusal {
+    pub reason: &'static str,
+    pub message: String,
+}
+
+/// How many refused connections may be answered at the same time, to kick their players with a message
+const KICK_SLOTS: usize = 4;
+
+/// What to do with a connection that was just accepted
+pub enum Admission {
+    /// Handle it normally
+    Accepted(Slot),
+    /// Only answer it to kick its player with the message of the refusal
+    Refused(Refusal, Slot),
+    /// Close it right away, as enough refused connections are being answered already
+    Dropped(Refusal),
+}
+
+/// Held by a connection until it is closed
+pub struct Slot {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+pub struct Limiter {
+    args: LimitArgs,
+    connections: Option<Arc<Semaphore>>,
+    kick_slots: Arc<Semaphore>,
+    connections_per_ip: Mutex<History<IpAddr>>,
+    starts_per_ip: Mutex<History<IpAddr>>,
+    starts_per_name: Mutex<History<String>>,
+    last_start: Mutex<Option<Instant>>,
+}
+
+impl Limiter {
+    pub fn new(args: LimitArgs) -> Self {
+        Self {
+            connections: args
+                .max_connections
+                .map(|max| Arc::new(Semaphore::new(max))),
+            kick_slots: Arc::new(Semaphore::new(KICK_SLOTS)),
+            connections_per_ip: Mutex::default(),
+            starts_per_ip: Mutex::default(),
+            starts_per_name: Mutex::default(),
+            last_start: Mutex::default(),
+            args,
+        }
+    }
+
+    /// Called for every connection while spoofing, as soon as it is accepted
+    pub fn admit_connection(&self, ip: IpAddr) -> Admission {
+        match self.connection_slot(ip) {
+            Ok(slot) => Admission::Accepted(slot),
+            Err(refusal) => match self.kick_slots.clone().try_acquire_owned() {
+                Ok(permit) => Admission::Refused(
+                    refusal,
+                    Slot {
+                        _permit: Some(permit),
+                    },
+                ),
+                Err(_) => Admission::Dropped(refusal),
+            },
+        }
+    }
+
+    fn connection_slot(&self, ip: IpAddr) -> Result<Slot, Refusal> {
+        if let Some(rate) = self.args.connection_rate {
+            if !lock(&self.connections_per_ip).allow(ip, rate, Instant::now()) {
+                return Err(Refusal {
+                    reason: "connection rate exceeded",
+                    message: self.args.connection_rate_message.clone(),
+                });
+            }
+        }
+
+        let permit = match self.connections {
+            Some(ref semaphore) => match semaphore.clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    return Err(Refusal {
+                        reason: "too many concurrent connections",
+                        message: self.args.max_connections_message.clone(),
+                    })
+                }
+            },
+            None => None,
+        };
+
+        Ok(Slot { _permit: permit })
+    }
+
+    /// Called when a player that is allowed to wake the server up asks to
+    pub fn admit_start_request(&self, ip: IpAddr, name: &str) -> Result<(), Refusal> {
+        if let Some(cooldown) = self.args.start_cooldown {
+            if let Some(last_start) = *lock(&self.last_start) {
+                let remaining = cooldown.saturating_sub(last_start.elapsed());
+                if !remaining.is_zero() {
+                    return Err(Refusal {
+                        reason: "start cooldown",
+                        message: cooldown_message(&self.args.start_cooldown_message, remaining),
+                    });
+                }
+            }
+        }
+
+        if let Some(rate) = self.args.start_request_rate {
+            let now = Instant::now();
+            let name = name.to_lowercase();
+            let mut starts_per_ip = lock(&self.starts_per_ip);
+            let mut starts_per_name = lock(&self.starts_per_name);
+            // Neither is charged for a request the other refuses
+            if !(starts_per_ip.within(&ip, rate, now) && starts_per_name.within(&name, rate, now)) {
+                return Err(Refusal {
+                    reason: "start request rate exceeded",
+                    message: self.args.start_request_rate_message.clone(),
+                });
+            }
+            starts_per_ip.record(ip, now);
+            starts_per_name.record(name, now);
+        }
+
+        Ok(())
+    }
+
+    /// Starts the cooldown. Called whenever the minecraft server is started
+    pub fn record_start(&self) {
+        *lock(&self.last_start) = Some(Instant::now());
+    }
+}
+
+/// The kick message of the start cooldown, with `{remaining}` replaced by the time left
+fn cooldown_message(template: &str, remaining: Duration) -> String {
+    // Round up so we never tell players to come back too early
+    let seconds = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+    template.replace(
+        "{remaining}",
+        &format_duration(Duration::from_secs(seconds)),
+    )
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .expect("no thread should panic while holding a limiter lock")
+}
+
+/// When each key was last seen, within the period of a rate limit
+struct History<K> {
+    events: HashMap<K, VecDeque<Instant>>,
+}
+
+impl<K> Default for History<K> {
+    fn default() -> Self {
+        Self {
+            events: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq> History<K> {
+    /// Records an event for `key` and returns whether it stays within `rate`.
+    /// Refused events are not recorded, so that hammering the limit doesn't extend it.
+    fn allow(&mut self, key: K, rate: RateLimit, now: Instant) -> bool {
+        let allowed = self.within(&key, rate, now);
+        if allowed {
+            self.record(key, now);
+        }
+        allowed
+    }
+
+    /// Whether one more event for `key` would stay within `rate`, forgetting the events older than its period
+    fn within(&mut self, key: &K, rate: RateLimit, now: Instant) -> bool {
+        self.events.retain(|_, times| {
+            while times
+                .front()
+                .is_some_and(|time| now.duration_since(*time) >= rate.period)
+            {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+
+        self.events.get(key).map_or(0, VecDeque::len) < rate.count
+    }
+
+    fn record(&mut self, key: K, now: Instant) {
+        self.events.entry(key).or_default().push_back(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(start_cooldown: Option<Duration>, start_request_rate: Option<&str>) -> Limiter {
+        Limiter::new(LimitArgs {
+            connection_rate: None,
+            max_connections: None,
+            start_cooldown,
+            start_request_rate: start_request_rate.map(|rate| rate.parse().unwrap()),
+            connection_rate_message: String::new(),
+            max_connections_message: String::new(),
+            start_cooldown_message: "come back in {remaining}".to_owned(),
+            start_request_rate_message: String::new(),
+        })
+    }
+
+    #[test]
+    fn rate_limit_test() {
+        let rate: RateLimit = "10/1m".parse().unwrap();
+        assert_eq!(10, rate.count);
+        assert_eq!(Duration::from_secs(60), rate.period);
+        assert_eq!(
+            Duration::from_secs(3),
+            "2/3".parse::<RateLimit>().unwrap().period
+        );
+        assert!("10".parse::<RateLimit>().is_err());
+        assert!("ten/1m".parse::<RateLimit>().is_err());
+        assert!("10/1 minute".parse::<RateLimit>().is_err());
+    }
+
+    #[test]
+    fn history_test() {
+        let rate: RateLimit = "2/1m".parse().unwrap();
+        let start = Instant::now();
+        let mut history = History::default();
+
+        assert!(history.allow("a", rate, start));
+        assert!(history.allow("a", rate, start + Duration::from_secs(30)));
+        assert!(!history.allow("a", rate, start + Duration::from_secs(40)));
+        // Other keys have their own count
+        assert!(history.allow("b", rate, start + Duration::from_secs(40)));
+        // The first event expired, and the refused one wasn't recorded
+        assert!(history.allow("a", rate, start + Duration::from_secs(60)));
+        assert!(!history.allow("a", rate, start + Duration::from_secs(89)));
+        assert!(history.allow("a", rate, start + Duration::from_secs(90)));
+    }
+
+    #[test]
+    fn cooldown_message_test() {
+        let template = "come back in {remaining}";
+        assert_eq!(
+            "come back in 10m",
+            cooldown_message(template, Duration::from_secs(600))
+        );
+        assert_eq!(
+            "come back in 10m",
+            cooldown_message(template, Duration::from_millis(599_001))
+        );
+        assert_eq!(
+            "come back in 1s",
+            cooldown_message(template, Duration::from_millis(1))
+        );
+    }
+
+    #[test]
+    fn start_cooldown_test() {
+        let limiter = limiter(Some(Duration::from_secs(600)), None);
+        let ip = IpAddr::from([127, 0, 0, 1]);
+        assert!(limiter.admit_start_request(ip, "Notch").is_ok());
+
+        limiter.record_start();
+        let refusal = limiter.admit_start_request(ip, "Notch").unwrap_err();
+        assert_eq!("start cooldown", refusal.reason);
+        assert_eq!("come back in 10m", refusal.message);
+    }
+
+    #[test]
+    fn start_request_rate_test() {
+        let limiter = limiter(None, Some("1/1h"));
+        let (first, second) = (IpAddr::from([127, 0, 0, 1]), IpAddr::from([127, 0, 0, 2]));
+
+        assert!(limiter.admit_start_request(first, "Notch").is_ok());
+        // The name is refused, so the second IP isn't charged
+        assert!(limiter.admit_start_request(second, "notch").is_err());
+        assert!(limiter.admit_start_request(second, "jeb_").is_ok());
+        assert!(limiter.admit_start_request(first, "Dinnerbone").is_err());
+    }
+
+    #[test]
+    fn admit_connection_test() {
+        let limiter = Limiter::new(LimitArgs {
+            max_connections: Some(1),
+            ..limiter(None, None).args
+        });
+        let ip = IpAddr::from([127, 0, 0, 1]);
+
+        let accepted = limiter.admit_connection(ip);
+        assert!(matches!(accepted, Admission::Accepted(_)));
+        let refused: Vec<_> = (0..KICK_SLOTS)
+            .map(|_| limiter.admit_connection(ip))
+            .collect();
+        assert!(refused
+            .iter()
+            .all(|admission| matches!(admission, Admission::Refused(..))));
+        assert!(matches!(
+            limiter.admit_connection(ip),
+            Admission::Dropped(_)
+        ));
+
+        // Closing connections frees their slots
+        drop(accepted);
+        drop(refused);
+        let accepted = limiter.admit_connection(ip);
+        assert!(matches!(accepted, Admission::Accepted(_)));
+        assert!(matches!(
+            limiter.admit_connection(ip),
+            Admission::Refused(..)
+        ));
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    future::poll_fn,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    task::Poll,
+    time::Duration,
+};
+
+use activitymanager::mc_protocol::proxy_protocol;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::{
+    io::{self, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    task,
+};
+
+/// Binds a listener on every interface. IPv6 listeners also accept IPv4 connections,
+/// unless an IPv4 interface is given as well, as both would then compete for the same port.
+pub fn bind_all(interfaces: &[IpAddr], port: u16) -> io::Result<Vec<TcpListener>> {
+    let dual_stack = !interfaces.iter().any(IpAddr::is_ipv4);
+    interfaces
+        .iter()
+        .map(|interface| {
+            let socket = bind(
+                SocketAddr::new(*interface, port),
+                dual_stack,
+                Type::STREAM,
+                Protocol::TCP,
+            )?;
+            socket.listen(1024)?;
+            TcpListener::from_std(socket.into())
+        })
+        .collect()
+}
+
+/// Like `bind_all`, for UDP sockets
+pub fn bind_all_udp(interfaces: &[IpAddr], port: u16) -> io::Result<Vec<UdpSocket>> {
+    let dual_stack = !interfaces.iter().any(IpAddr::is_ipv4);
+    interfaces
+        .iter()
+        .map(|interface| {
+            let socket = bind(
+                SocketAddr::new(*interface, port),
+                dual_stack,
+                Type::DGRAM,
+                Protocol::UDP,
+            )?;
+            UdpSocket::from_std(socket.into())
+        })
+        .collect()
+}
+
+fn bind(
+    address: SocketAddr,
+    dual_stack: bool,
+    kind: Type,
+    protocol: Protocol,
+) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(address), kind, Some(protocol))?;
+    if address.is_ipv6() {
+        socket.set_only_v6(!dual_stack)?;
+    }
+    // Like `TcpListener::bind`, so we can take the port back right after the minecraft server releases it
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    Ok(socket)
+}
+
+/// Accepts the next connection on any of the listeners.
+/// Addresses of IPv4 clients on dual-stack listeners are turned back into IPv4 addresses.
+pub async fn accept(listeners: &[TcpListener]) -> io::Result<(TcpStream, SocketAddr)> {
+    let (stream, address) = poll_fn(|cx| {
+        for listener in listeners {
+            if let Poll::Ready(result) = listener.poll_accept(cx) {
+                return Poll::Ready(result);
+            }
+        }
+        Poll::Pending
+    })
+    .await?;
+    Ok((
+        stream,
+        SocketAddr::new(address.ip().to_canonical(), address.port()),
+    ))
+}
+
+/// Where the minecraft server can be reached when it listens on `interface`.
+/// Servers listening on every interface are reached through the IPv4 loopback, which dual-stack sockets accept too.
+pub fn local_address(interface: IpAddr, port: u16) -> SocketAddr {
+    if interface.is_unspecified() {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port)
+    } else {
+        SocketAddr::new(interface, port)
+    }
+}
+
+/// Forwards every connection accepted on `listeners` to `backend`.
+/// Used while the minecraft server runs whenever we hold on to the listeners, since it can't bind them itself:
+/// when they came from systemd, and with `--idle-action freeze`, as frozen servers keep their port.
+///
+/// Connections then come from us for the minecraft server, unless `send_proxy_header` precedes them with
+/// a PROXY protocol header giving the address of the client.
+pub async fn proxy(listeners: Arc<Vec<TcpListener>>, backend: SocketAddr, send_proxy_header: bool) {
+    loop {
+        let (mut client, address) = match accept(&listeners).await {
+            Ok(connection) => connection,
+            Err(err) => {
+                println!("\x1b[38;5;11mWarning: Couldn't accept a connection to forward. Got err: {err}\x1b[0m");
+                // Errors like running out of file descriptors won't go away immediately
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        task::spawn(async move {
+            match TcpStream::connect(backend).await {
+                Ok(mut server) => {
+                    if send_proxy_header {
+                        let header = match client.local_addr() {
+                            Ok(local_address) => proxy_protocol::v2_header(address, local_address),
+                            Err(err) => {
+                                println!("\x1b[38;5;11mWarning: Couldn't forward connection from {address}: its local address is unknown. Got err: {err}\x1b[0m");
+                                return;
+                            }
+                        };
+                        if server.write_all(&header).await.is_err() {
+                            return;
+                        }
+                    }
+                    let _ = io::copy_bidirectional(&mut client, &mut server).await;
+                }
+                Err(err) => println!(
+                    "\x1b[38;5;11mWarning: Couldn't forward connection from {address} to the minecraft server at {backend}. Got err: {err}\x1b[0m"
+                ),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use socket2::SockRef;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    const LOCALHOST_V4: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    const UNSPECIFIED_V6: IpAddr = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+
+    /// A port nothing listens on, for binding several sockets to the same one
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    #[tokio::test]
+    async fn bind_all_test() {
+        // Alone, the IPv6 listener takes IPv4 connections too
+        let listeners = bind_all(&[UNSPECIFIED_V6], 0).unwrap();
+        assert!(!SockRef::from(&listeners[0]).only_v6().unwrap());
+        let port = listeners[0].local_addr().unwrap().port();
+        let client = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .unwrap();
+        let (_, address) = accept(&listeners).await.unwrap();
+        assert_eq!(client.local_addr().unwrap(), address);
+
+        let port = free_port();
+        let listeners = bind_all(&[IpAddr::V4(Ipv4Addr::UNSPECIFIED), UNSPECIFIED_V6], port)
+            .expect("the IPv6 listener shouldn't compete with the IPv4 one for the port");
+        assert!(SockRef::from(&listeners[1]).only_v6().unwrap());
+    }
+
+    #[tokio::test]
+    async fn bind_all_udp_test() {
+        let sockets = bind_all_udp(&[UNSPECIFIED_V6], 0).unwrap();
+        assert!(!SockRef::from(&sockets[0]).only_v6().unwrap());
+        let port = sockets[0].local_addr().unwrap().port();
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(b"ping", (LOCALHOST_V4, port)).await.unwrap();
+        let mut buffer = [0; 8];
+        let (length, _) = sockets[0].recv_from(&mut buffer).await.unwrap();
+        assert_eq!(b"ping", &buffer[..length]);
+
+        let port = free_port();
+        let sockets =
+            bind_all_udp(&[IpAddr::V4(Ipv4Addr::UNSPECIFIED), UNSPECIFIED_V6], port).unwrap();
+        assert!(SockRef::from(&sockets[1]).only_v6().unwrap());
+    }
+
+    #[tokio::test]
+    async fn accept_test() {
+        let listeners = bind_all(&[LOCALHOST_V4, LOCALHOST_V4], 0).unwrap();
+        for listener in listeners.iter().rev() {
+            let client = TcpStream::connect(listener.local_addr().unwrap())
+                .await
+                .unwrap();
+            let (stream, address) = accept(&listeners).await.unwrap();
+            assert_eq!(client.local_addr().unwrap(), address);
+            assert_eq!(listener.local_addr().unwrap(), stream.local_addr().unwrap());
+        }
+    }
+
+    #[test]
+    fn local_address_test() {
+        assert_eq!(
+            SocketAddr::new(LOCALHOST_V4, 25565),
+            local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 25565)
+        );
+        assert_eq!(
+            SocketAddr::new(LOCALHOST_V4, 25565),
+            local_address(UNSPECIFIED_V6, 25565)
+        );
+        let interface = "192.0.2.1".parse().unwrap();
+        assert_eq!(
+            SocketAddr::new(interface, 25565),
+            local_address(interface, 25565)
+        );
+    }
+
+    #[tokio::test]
+    async fn proxy_test() {
+        let listeners = Arc::new(bind_all(&[LOCALHOST_V4], 0).unwrap());
+        let proxy_address = listeners[0].local_addr().unwrap();
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = task::spawn(proxy(listeners, backend.local_addr().unwrap(), true));
+
+        let mut client = TcpStream::connect(proxy_address).await.unwrap();
+        client.write_all(b"hello").await.unwrap();
+        let (mut server, _) = backend.accept().await.unwrap();
+
+        let header = proxy_protocol::v2_header(client.local_addr().unwrap(), proxy_address);
+        let mut received = vec![0; header.len() + 5];
+        server.read_exact(&mut received).await.unwrap();
+        assert_eq!(
+            Some((Some(client.local_addr().unwrap()), header.len())),
+            proxy_protocol::decode_header(&received).unwrap()
+        );
+        assert_eq!(b"hello", &received[header.len()..]);
+
+        server.write_all(b"world").await.unwrap();
+        let mut received = [0; 5];
+        client.read_exact(&mut received).await.unwrap();
+        assert_eq!(b"world", &received);
+        proxy.abort();
+    }
+}
@@ -1,27 +1,51 @@
-mod mc_protocol;
-use mc_protocol::{
+mod backend;
+mod backup;
+mod bedrock;
+mod console;
+mod duration;
+mod hooks;
+mod idle;
+mod journal;
+mod limits;
+mod listen;
+mod query;
+mod schedule;
+mod server_lists;
+mod systemd;
+mod webhooks;
+use activitymanager::mc_protocol::{
     clientbound_packets::v760_packets as clientbound,
     data_types::{get_length_prefixed_reader, LengthPrefixed, McVarint},
-    serverbound_packets::{generic_packets, v760_packets as serverbound, Serverbound},
-    McProtocol, ProtocolVersion, ServerCodec,
+    serverbound_packets::{generic_packets, v760_packets as serverbound, ServerboundLogin},
+    Decode, Encode, Forwarding, Handshake, ProtocolVersion, ServerCodec, ServerboundPacket,
 };
+use backend::{Backend, BackendArgs, Exit};
+use backup::BackupArgs;
+use bedrock::BedrockArgs;
+use hooks::{Hook, Hooks};
+use idle::{IdleAction, IdleArgs, IdleTracker};
+use journal::{Event, Journal};
+use limits::{Admission, LimitArgs, Limiter};
+use query::{PlayerCountSource, QueryArgs};
+use schedule::Schedule;
+use server_lists::{parse_lists, read_server_properties, watch_lists, ServerLists};
+use webhooks::{WebhookArgs, Webhooks};
 
 use std::{
-    net::{Ipv4Addr, SocketAddrV4},
-    path::{Path, PathBuf},
-    process::Stdio,
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    num::NonZeroUsize,
+    path::PathBuf,
     sync::Arc,
     time::{Duration, Instant},
 };
 use tokio::{
-    fs,
-    io::{self, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
-    net::{TcpListener, TcpStream},
-    process::Command,
+    io::{self, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
+    net::TcpStream,
     task,
 };
 
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
 
 use chrono::Local;
 
@@ -34,205 +58,599 @@ use chrono::Local;
 When no players have been online for more than the specified timeout, the minecraft server will be closed and activity manager will listen for incoming connections.
 When someone tries to connect to the minecraft server, it will be started again.
 
-Stdin is forwarded to the minecraft server, so you can still send commands. However, it is interpreted slightly:
+Stdin (and the --console-pipe, if any) is forwarded to the minecraft server, so you can still send commands. However, it is interpreted slightly:
 - 'stop' will stop the minecraft server but also shut down the activity manager. This means it won't boot up automatically again.
    This is intended as a compatibility feature for any other managment script that might expect 'stop' to stop the whole process.
 - 'spoof' will stop the minecraft server and enter the spoofing stage. It will start again when it recieves a connection.
-- 'start' only works in the spoofing stage and starts the minecraft server whether someone tried to connect or not"#
+- 'start' only works in the spoofing stage and starts the minecraft server whether someone tried to connect or not
+
+Schedule windows (--force-on, --refuse-wake, --timeout-during) are written as '<days> <HH:MM>-<HH:MM>',
+where days can be '*', 'fri', 'mon-fri' or 'sat,sun'. When the end is before the start, the window spans midnight.
+
+Under systemd, the state of the minecraft server is reported with sd_notify (use Type=notify) and the watchdog is pinged when enabled.
+With socket activation, the sockets passed by systemd replace --interface and --port: the minecraft server must listen elsewhere,
+on --probe-address, and connections are forwarded to it while it runs."#,
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
 )]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// path to a script that starts your minecraft server
-    start_script: PathBuf,
+    #[arg(required_unless_present = "start_command")]
+    start_script: Option<PathBuf>,
 
     /// the port your minecraft server listens on
     #[arg(long, short, default_value_t = 25565)]
     port: u16,
 
-    /// the interface your minecraft server listens on
-    #[arg(long, short, default_value_t = Ipv4Addr::new(0, 0, 0, 0))]
-    interface: Ipv4Addr,
+    /// the interfaces your minecraft server listens on, IPv4 or IPv6 (can be repeated).
+    /// An IPv6 interface also accepts IPv4 connections when no IPv4 interface is given, so '::' listens on everything.
+    #[arg(long, short, default_values_t = [IpAddr::V4(Ipv4Addr::UNSPECIFIED)])]
+    interface: Vec<IpAddr>,
+
+    /// where to reach your minecraft server to query its player count [default: the first interface and the port].
+    /// Required with socket activation. Connections forwarded there then come from activity manager, see --send-proxy-protocol
+    #[arg(long, value_name = "ADDRESS:PORT")]
+    probe_address: Option<SocketAddr>,
 
-    /// the period of time (in minutes) activity manager will consider to be inactivity
-    #[arg(long, short, default_value_t = 5)]
-    timeout: u32,
+    /// precede the connections forwarded to --probe-address (with socket activation or --idle-action freeze)
+    /// with a PROXY protocol v2 header, so the minecraft server sees the address of players instead of ours
+    /// for its IP bans, plugins and logs. The server must expect it, e.g. with proxy-protocol in Paper's config.
+    /// With --proxy-protocol, the headers of the proxy in front are already forwarded as they are
+    #[arg(long, conflicts_with = "proxy_protocol")]
+    send_proxy_protocol: bool,
+
+    /// expect connections to start with a PROXY protocol header (v1 or v2), as sent by HAProxy and most TCP load balancers.
+    /// Connections without one are refused
+    #[arg(long)]
+    proxy_protocol: bool,
+
+    /// trust the player address and uuid BungeeCord forwards in handshakes (its ip_forward option).
+    /// Logins without them are refused
+    #[arg(long)]
+    bungeecord: bool,
 
     /// Root folder of your minecraft server.
     #[arg(long, short = 'r')]
     server_root: Option<PathBuf>,
 
     /// if set, activity manager will only start the minecraft server for players present in the provided whitelist.json or ops.json.
+    /// Players are matched by uuid, or by name when the server's online-mode is disabled or the client sends no uuid.
     #[arg(long, short, requires = "server_root")]
     whitelist: bool,
+
+    /// if set, wake-ups, stops and crashes of the minecraft server will be appended to this file.
+    #[arg(long, short)]
+    journal: Option<PathBuf>,
+
+    /// named pipe console commands are also read from, created if it doesn't exist.
+    /// Lets you send commands when running without a terminal, e.g. `echo spoof > PATH`
+    #[arg(long, value_name = "PATH")]
+    console_pipe: Option<PathBuf>,
+
+    /// unix socket remote consoles can attach to with the `attach` subcommand.
+    /// They are shown recent output, then everything printed by activity manager and the minecraft server
+    #[arg(long, value_name = "PATH")]
+    console_socket: Option<PathBuf>,
+
+    /// number of lines of output replayed to remote consoles when they attach
+    #[arg(long, value_name = "LINES", default_value = "1000")]
+    scrollback: NonZeroUsize,
+
+    #[command(flatten)]
+    idle: IdleArgs,
+
+    #[command(flatten)]
+    schedule: Schedule,
+
+    #[command(flatten)]
+    limits: LimitArgs,
+
+    #[command(flatten)]
+    hooks: Hooks,
+
+    #[command(flatten)]
+    backup: BackupArgs,
+
+    #[command(flatten)]
+    webhooks: WebhookArgs,
+
+    #[command(flatten)]
+    backend: BackendArgs,
+
+    #[command(flatten)]
+    query: QueryArgs,
+
+    #[command(flatten)]
+    bedrock: BedrockArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// List the events recorded in a journal
+    Journal(journal::JournalArgs),
+    /// Attach to the console of a running activity manager started with --console-socket
+    Attach {
+        /// the console socket of the activity manager
+        socket: PathBuf,
+    },
 }
 
 const LOGIN_RESPONSE: &str = r#"[{"text":"Serveur Hors Ligne\n\n","color":"red"},{"text":"Demande de démarrage reçue,\nle serveur devrait être disponible d'ici une minute","color":"white"}]"#;
 const STATUS_RESPONSE: &str = r#"{"description":[{"text":"Hors Ligne\n","color":"dark_red"},{"text":"Connectez vous pour démarrer le serveur","color":"dark_green"}],"version":{"name":"1.19.2","protocol":760}}"#;
 
+const BEDROCK_TITLE: &str = "Hors Ligne";
+const BEDROCK_SUBTITLE: &str = "Connectez vous pour démarrer le serveur";
+const QUERY_MOTD: &str = "Hors Ligne - Connectez vous pour démarrer le serveur";
+const RESUME_RESPONSE: &str = r#"[{"text":"Serveur en veille\n\n","color":"gold"},{"text":"Le serveur reprend,\nreconnectez-vous dans quelques secondes","color":"white"}]"#;
+
+/// Fields of the status of the minecraft server that are added to ours.
+/// Forge 1.13+ uses `forgeData`, older versions `modinfo`
+const MOD_INFO_FIELDS: [&str; 2] = ["forgeData", "modinfo"];
+
+const REFUSED_WAKE_RESPONSE: &str = "The server can't be started right now, try again later";
+
 const TIME_FORMAT: &str = "[%H:%M:%S]";
 
+/// How long a connection may take to send its handshake
+const FIRST_PACKET_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a connection refused by the limiter may stay open, handshake included
+const REFUSED_CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Wraps plain text in a JSON chat component, for use in disconnect messages
+fn text_component(text: &str) -> String {
+    serde_json::json!({ "text": text }).to_string()
+}
+
+fn main() {
+    // Before the runtime could start any thread, as changing the environment isn't thread safe
+    let systemd_environment = systemd::Environment::take();
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("the runtime should build")
+        .block_on(run(systemd_environment));
+}
+
 #[allow(clippy::single_match)]
-#[tokio::main(flavor = "current_thread")]
-async fn main() {
-    let (stdin_sender, mut stdin_reciever) = tokio::sync::mpsc::channel::<String>(10);
-
-    task::spawn(async move {
-        let stdin = tokio::io::stdin();
-        let mut stdin_reader = BufReader::new(stdin);
-        let mut line_buffer = String::new();
-        loop {
-            stdin_reader
-                .read_line(&mut line_buffer)
-                .await
-                .expect("should have been able to read from stdin");
-            stdin_sender
-                .send(line_buffer.clone())
-                .await
-                .expect("channel shouldn't close");
-            if &line_buffer == "stop\n" {
-                break;
-            };
-            line_buffer.clear();
+async fn run(mut systemd_environment: systemd::Environment) {
+    let args = Cli::parse();
+
+    match args.command {
+        Some(Commands::Journal(journal_args)) => {
+            if let Err(err) = journal::list(journal_args).await {
+                println!("\x1b[38;5;11mCritical: Couldn't read journal. Got err: {err}\x1b[0m");
+                console::exit(1).await;
+            }
+            return;
+        }
+        Some(Commands::Attach { socket }) => {
+            if let Err(err) = console::attach(&socket).await {
+                println!(
+                    "\x1b[38;5;11mCritical: Couldn't attach to {}. Got err: {err}\x1b[0m",
+                    socket.display()
+                );
+                console::exit(1).await;
+            }
+            return;
         }
-    });
+        None => {}
+    }
 
-    let args = Cli::parse();
+    let activated_listeners = match systemd::take_listeners(&mut systemd_environment) {
+        Ok(listeners) => listeners.map(Arc::new),
+        Err(err) => {
+            println!("\x1b[38;5;11mCritical: Couldn't use the sockets passed by systemd. Got err: {err}\x1b[0m");
+            console::exit(1).await;
+        }
+    };
+    // By default it is the port systemd gave us, so connections would be forwarded back to ourselves
+    if activated_listeners.is_some() && args.probe_address.is_none() {
+        Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--probe-address is required with socket activation, as the minecraft server can't listen on the sockets passed by systemd",
+            )
+            .exit();
+    }
+    let notifier = Arc::new(systemd::Notifier::new(&systemd_environment));
+    notifier.spawn_watchdog();
+
+    let mut backend = backend::from_args(args.start_script.clone(), args.backend);
+
+    let journal = Arc::new(Journal::new(args.journal.clone()));
+    let hooks = Arc::new(args.hooks);
+    let webhooks = Arc::new(Webhooks::new(args.webhooks));
+    let schedule = Arc::new(args.schedule);
+    let limiter = Arc::new(Limiter::new(args.limits));
 
-    let socket = SocketAddrV4::new(args.interface, args.port);
+    // The mods of the minecraft server, from the last status it answered with
+    let (mod_info_sender, mod_info) = tokio::sync::watch::channel(Arc::new(serde_json::Map::new()));
+
+    // Kept up to date by a background task, so changes apply without waiting for the next spoofing cycle
+    let server_lists = match args.server_root {
+        Some(ref server_root) => {
+            match parse_lists(server_root, args.whitelist).await {
+                Ok(lists) => watch_lists(server_root.clone(), args.whitelist, lists),
+                Err(err) => {
+                    println!("\x1b[38;5;11mCritical: Couldn't read the player lists. Got err: {err}\x1b[0m");
+                    console::exit(1).await;
+                }
+            }
+        }
+        None => tokio::sync::watch::channel(Arc::new(ServerLists::default())).1,
+    };
+
+    // Lines typed in the terminal or written to the console pipe
+    let (console_sender, mut console_reciever) = tokio::sync::mpsc::channel::<String>(10);
+
+    if let Some(ref console_socket) = args.console_socket {
+        let started = console::capture_output(args.scrollback).and_then(|output| {
+            console::spawn_socket(console_socket.clone(), output, console_sender.clone())
+        });
+        if let Err(err) = started {
+            println!(
+                "\x1b[38;5;11mCritical: Couldn't open the console socket {}. Got err: {err}\x1b[0m",
+                console_socket.display()
+            );
+            console::exit(1).await;
+        }
+    }
+    if let Some(ref console_pipe) = args.console_pipe {
+        if let Err(err) = console::spawn_pipe(console_pipe.clone(), console_sender.clone()) {
+            println!(
+                "\x1b[38;5;11mCritical: Couldn't open the console pipe {}. Got err: {err}\x1b[0m",
+                console_pipe.display()
+            );
+            console::exit(1).await;
+        }
+    }
+    console::spawn_stdin(console_sender);
+
+    let probe_address = args
+        .probe_address
+        .unwrap_or_else(|| listen::local_address(args.interface[0], args.port));
+    let query_address = args.query.address(probe_address);
+
+    // A frozen server keeps its port, so we keep ours for the whole run and forward connections to it while it runs
+    let persistent_listeners = match activated_listeners {
+        Some(listeners) => Some(listeners),
+        None if args.idle.action() == IdleAction::Freeze => {
+            match listen::bind_all(&args.interface, args.port) {
+                Ok(listeners) => Some(Arc::new(listeners)),
+                Err(err) => {
+                    println!("\x1b[38;5;11mCritical: Could not bind to port {} on {:?}. Got error: {err}\x1b[0m", args.port, args.interface);
+                    println!("\x1b[38;5;11mPlease ensure the interface and port are valid and not used by any other program\x1b[0m");
+                    console::exit(1).await;
+                }
+            }
+        }
+        None => None,
+    };
+
+    let forwarding = Forwarding {
+        proxy_protocol: args.proxy_protocol,
+        bungeecord: args.bungeecord,
+    };
+
+    // Set while the minecraft server is suspended. Players then resume it instead of starting it
+    let mut frozen = false;
+
+    // A backup taken after the last stop. The server isn't started again before it is done
+    let mut backup: Option<task::JoinHandle<()>> = None;
+
+    // e.g. when its container kept running while activity manager restarted
+    let mut already_running = match backend.is_running().await {
+        Ok(running) => running,
+        Err(err) => {
+            println!("\x1b[38;5;11mWarning: Couldn't tell whether the minecraft server is running. Got err: {err}\x1b[0m");
+            false
+        }
+    };
 
     loop {
-        {
-            let listener = match TcpListener::bind(socket).await {
-                Ok(listener) => listener,
+        if !already_running {
+            let listeners = match persistent_listeners {
+                Some(ref listeners) => Ok(listeners.clone()),
+                None => listen::bind_all(&args.interface, args.port).map(Arc::new),
+            };
+            let listeners = match listeners {
+                Ok(listeners) => listeners,
                 Err(err) => {
-                    println!("\x1b[38;5;11mCritical: Could not bind to socket {socket}. Got error: {err}\x1b[0m");
+                    println!("\x1b[38;5;11mCritical: Could not bind to port {} on {:?}. Got error: {err}\x1b[0m", args.port, args.interface);
                     println!("\x1b[38;5;11mPlease ensure the interface and port are valid and not used by any other program\x1b[0m");
-                    std::process::exit(1);
+                    console::exit(1).await;
                 }
             };
 
-            let whitelist = if args.whitelist {
-                match parse_whitelist(
-                    args.server_root
-                        .as_ref()
-                        .expect("server root should be provided when enabling whitelist"),
-                )
-                .await
-                {
-                    Ok(whitelist) => Some(Arc::new(whitelist)),
-                    Err(WhitelistParseError::IO(err)) => {
-                        println!(
-                            "\x1b[38;5;11mCritical: Couldn't read whitelist. Got err: {err}\x1b[0m"
-                        );
-                        std::process::exit(1);
+            println!(
+                "\n\x1b[38;2;0;200;0mSpoofer listening on {}\x1b[0m\n",
+                listeners
+                    .iter()
+                    .filter_map(|listener| listener.local_addr().ok())
+                    .map(|address| address.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            notifier.notify(if frozen {
+                "READY=1\nSTATUS=Minecraft server frozen, waiting for players"
+            } else {
+                "READY=1\nSTATUS=Minecraft server asleep, waiting for players"
+            });
+
+            // Read again every time, as they may have been changed while the server ran
+            let properties = match args.server_root {
+                Some(ref server_root) => read_server_properties(server_root)
+                    .await
+                    .unwrap_or_default(),
+                None => HashMap::new(),
+            };
+
+            // The minecraft server answers queries itself once it runs
+            let query_responder = match args.query.port() {
+                Some(port) => match listen::bind_all_udp(&args.interface, port) {
+                    Ok(sockets) => {
+                        let stat = sleeping_stat(&properties, args.interface[0], args.port);
+                        Some(query::spawn_responder(sockets, stat))
                     }
-                    Err(WhitelistParseError::ParseJson(err)) => {
-                        println!("\x1b[38;5;11mCritical: Whitelist conatined invalid JSON. Got err: {err}\x1b[0m");
-                        std::process::exit(1);
+                    Err(err) => {
+                        println!("\x1b[38;5;11mWarning: Couldn't bind the query port {port}, queries won't be answered. Got err: {err}\x1b[0m");
+                        None
                     }
-                }
-            } else {
-                None
+                },
+                None => None,
             };
 
-            println!(
-                "\n\x1b[38;2;0;200;0mSpoofer listening on port {}\x1b[0m\n",
-                args.port
-            );
+            // Bedrock clients trying to connect. Never resolves when bedrock clients aren't answered
+            let (bedrock_sender, mut bedrock_reciever) =
+                tokio::sync::mpsc::channel::<SocketAddr>(1);
+            let bedrock_responder = match args.bedrock.port() {
+                Some(port) => match listen::bind_all_udp(&args.interface, port) {
+                    Ok(sockets) => {
+                        let motd = bedrock::Motd {
+                            title: BEDROCK_TITLE.to_owned(),
+                            subtitle: BEDROCK_SUBTITLE.to_owned(),
+                            max_players: max_players(&properties),
+                            port,
+                        };
+                        Some(bedrock::spawn_responder(sockets, motd, bedrock_sender))
+                    }
+                    Err(err) => {
+                        println!("\x1b[38;5;11mWarning: Couldn't bind the bedrock port {port}, bedrock clients won't be answered. Got err: {err}\x1b[0m");
+                        None
+                    }
+                },
+                None => None,
+            };
+
+            let (start_sender, mut start_reciever) = tokio::sync::mpsc::channel::<Event>(1);
 
-            let (start_sender, mut start_reciever) = tokio::sync::mpsc::channel::<()>(1);
+            let mut schedule_check = tokio::time::interval(Duration::from_secs(30));
 
             // We handle connections and loop until we recieve a Login request
             loop {
-                if tokio::select!(
-                    Ok((stream, address)) = listener.accept() => {
+                if let Some(start_event) = tokio::select!(
+                    Ok((stream, address)) = listen::accept(&listeners) => {
+                        let peer_address = format!("\x1b[38;5;14m{address}\x1b[0m");
+                        println!("{} Connection from {}", Local::now().format(TIME_FORMAT), peer_address);
+
+                        // Refused connections are only answered if they try to log in, so they can be kicked with a message
+                        let (slot, refusal) = match limiter.admit_connection(address.ip()) {
+                            Admission::Accepted(slot) => (slot, None),
+                            Admission::Refused(refusal, slot) => {
+                                println!("{} {peer_address} → Refused connection: {}", Local::now().format(TIME_FORMAT), refusal.reason);
+                                (slot, Some(refusal))
+                            }
+                            Admission::Dropped(refusal) => {
+                                println!("{} {peer_address} → Dropped connection: {}", Local::now().format(TIME_FORMAT), refusal.reason);
+                                continue;
+                            }
+                        };
+                        let accepted_at = tokio::time::Instant::now();
+
                         let start_sender = start_sender.clone();
 
-                        let whitelist = whitelist.clone();
+                        let server_lists = server_lists.borrow().clone();
+                        let mod_info = mod_info.borrow().clone();
+
+                        let schedule = schedule.clone();
+
+                        let limiter = limiter.clone();
+                        let journal = journal.clone();
+                        let hooks = hooks.clone();
+                        let webhooks = webhooks.clone();
+                        let frozen = frozen;
 
                         task::spawn(async move {
-                            let address = format!("\x1b[38;5;14m{address}\x1b[0m");
-                            println!("{} Connection from {}", Local::now().format(TIME_FORMAT), address);
+                            let _slot = slot;
+                            let peer = address;
+
+                            let codec = ServerCodec::new(stream, forwarding);
+
+                            // Proxies tell who the connection comes from at its start, so we need it before anything else
+                            let handshake = match tokio::time::timeout_at(accepted_at + FIRST_PACKET_TIMEOUT, codec.read_handshake()).await {
+                                Ok(Ok(handshake)) => handshake,
+                                Ok(Err(err)) => {
+                                    println!("{} Killed connection to {peer_address} on error: {err}", Local::now().format(TIME_FORMAT));
+                                    return;
+                                }
+                                Err(_) => {
+                                    println!("{} Killed connection to {peer_address}: it didn't send a handshake in time", Local::now().format(TIME_FORMAT));
+                                    return;
+                                }
+                            };
+                            let (client, client_ip) = match (handshake.bungee_forwarding(), handshake.proxied_address()) {
+                                // BungeeCord doesn't forward the port of the client
+                                (Some(forwarded), _) => (forwarded.address.to_string(), forwarded.address),
+                                (None, Some(proxied)) => {
+                                    let proxied = SocketAddr::new(proxied.ip().to_canonical(), proxied.port());
+                                    (proxied.to_string(), proxied.ip())
+                                }
+                                (None, None) => (peer.to_string(), peer.ip()),
+                            };
+                            let forwarded_uuid = handshake.bungee_forwarding().map(|forwarded| forwarded.uuid);
 
+                            let address = format!("\x1b[38;5;14m{client}\x1b[0m");
                             let status = |message: &str| {
                                 println!("{} {} → {}", Local::now().format(TIME_FORMAT), &address, message);
                             };
+                            if client != peer.to_string() {
+                                status(&format!("Forwarded by {peer_address}"));
+                            }
 
-                            let mut codec = ServerCodec::new(stream);
-
-                            let output = async {loop {match codec.read_packet().await? {
-                                Serverbound::Generic(packet) => match packet {
-                                    generic_packets::Generic::ServerListPing(_) => {
+                            let output = async {
+                                let log_handshake = |handshake: &generic_packets::HandshakePacket| {
+                                    if let Some(marker) = handshake.fml_marker() {
+                                        status(&format!("Client is modded ({marker})"));
+                                    }
+                                };
+                                let unsupported_version = |protocol_version: ProtocolVersion| io::Error::new(
+                                    io::ErrorKind::InvalidData,
+                                    format!("unsupported protocol version: {protocol_version}")
+                                );
+                                match handshake {
+                                    Handshake::ServerListPing { .. } => {
                                         status("Recieved legacy server list ping");
-                                        break Ok(false)
+                                        Ok(None)
                                     }
-                                    _ => {},
-                                },
-                                Serverbound::V760(packet) => match packet {
-                                    serverbound::V760::Status(packet) => {match packet {
-                                        serverbound::StatusPacket::StatusRequest{} => {
-                                            status("Requested status");
-                                            codec.send_packet(clientbound::StatusPacket::StatusResponse{ json_response: String::from(STATUS_RESPONSE) }).await?;
-                                            status("Sent status");
-                                        },
-                                        serverbound::StatusPacket::PingRequest{ payload } => {
-                                            status("Requested ping");
-                                            codec.send_packet(clientbound::StatusPacket::PingResponse{ payload }).await?;
-                                            status("Sent pong");
-                                            break Ok(false)
-                                        },
-                                    }},
-                                    serverbound::V760::Login(packet) => {match packet {
-                                        serverbound::LoginPacket::LoginStart { name, sig_data: _, player_uuid } => {
-                                            status(&format!(
-                                                "Recieved login request from \x1b[38;5;14m{name}\x1b[0m{}",
-                                                if let Some(uuid) = player_uuid {
-                                                    format!(" with uuid: \x1b[38;5;14m{uuid:x}\x1b[0m")
-                                                } else {
-                                                    "".to_owned()
+                                    Handshake::Status(handshake, mut codec) => {
+                                        log_handshake(&handshake);
+                                        if codec.protocol_version() != ProtocolVersion::V760 {
+                                            return Err(unsupported_version(codec.protocol_version()));
+                                        }
+                                        loop {match codec.read_packet().await? {
+                                            serverbound::StatusPacket::StatusRequest{} if refusal.is_some() => {
+                                                break Ok(None)
+                                            },
+                                            serverbound::StatusPacket::StatusRequest{} => {
+                                                status("Requested status");
+                                                codec.send_packet(clientbound::StatusPacket::StatusResponse{ json_response: status_response(&mod_info) }).await?;
+                                                status("Sent status");
+                                            },
+                                            serverbound::StatusPacket::PingRequest{ payload } => {
+                                                status("Requested ping");
+                                                codec.send_packet(clientbound::StatusPacket::PingResponse{ payload }).await?;
+                                                status("Sent pong");
+                                                break Ok(None)
+                                            },
+                                        }}
+                                    }
+                                    Handshake::Login(handshake, mut codec) => {
+                                        log_handshake(&handshake);
+                                        if codec.protocol_version() != ProtocolVersion::V760 {
+                                            return Err(unsupported_version(codec.protocol_version()));
+                                        }
+                                        match codec.read_packet().await? {
+                                            ServerboundLogin::V760(serverbound::LoginPacket::LoginStart { name, sig_data: _, player_uuid }) => {
+                                                // Players behind BungeeCord send the uuid it gave them, if any
+                                                let player_uuid = forwarded_uuid.or(player_uuid);
+                                                let start_event = Event::StartRequested {
+                                                    player: name.clone(),
+                                                    uuid: player_uuid.map(|uuid| format!("{uuid:032x}")),
+                                                    address: client.clone(),
+                                                };
+                                                let rejection = |reason: &str| Event::WakeRejected {
+                                                    player: name.clone(),
+                                                    uuid: player_uuid.map(|uuid| format!("{uuid:032x}")),
+                                                    address: client.clone(),
+                                                    reason: reason.to_owned(),
+                                                };
+
+                                                status(&format!(
+                                                    "Recieved login request from \x1b[38;5;14m{name}\x1b[0m{}",
+                                                    if let Some(uuid) = player_uuid {
+                                                        format!(" with uuid: \x1b[38;5;14m{uuid:x}\x1b[0m")
+                                                    } else {
+                                                        "".to_owned()
+                                                    }
+                                                ));
+
+                                                if let Some(ref refusal) = refusal {
+                                                    codec.send_packet(clientbound::LoginPacket::Disconnect {
+                                                        reason: text_component(&refusal.message)
+                                                    }).await?;
+                                                    status("Disconnected player");
+                                                    return Ok(Some(rejection(refusal.reason)))
                                                 }
-                                            ));
-
-                                            if let Some(ref whitelist) = whitelist {
-                                                if let Some(uuid) = player_uuid {
-                                                    if whitelist.contains(&uuid) {
-                                                        codec.send_packet(clientbound::LoginPacket::Disconnect { reason: String::from(LOGIN_RESPONSE) }).await?;
-                                                        status(&format!("\x1b[38;5;14m{name}\x1b[0m is whitelisted. Disconnected player"));
-                                                        break Ok(true)
+
+                                                if let Some(message) = server_lists.bans.check(player_uuid, &name, client_ip) {
+                                                    codec.send_packet(clientbound::LoginPacket::Disconnect {
+                                                        reason: text_component(&message)
+                                                    }).await?;
+                                                    status(&format!("\x1b[38;5;14m{name}\x1b[0m is banned. Disconnected player"));
+                                                    return Ok(Some(rejection("banned")))
+                                                }
+
+                                                if let Some(message) = schedule.wake_refusal(&Local::now()) {
+                                                    codec.send_packet(clientbound::LoginPacket::Disconnect {
+                                                        reason: text_component(message.unwrap_or(REFUSED_WAKE_RESPONSE))
+                                                    }).await?;
+                                                    status("Wake-ups are currently refused by the schedule. Disconnected player");
+                                                    return Ok(Some(rejection("refused by the schedule")))
+                                                }
+
+                                                if let Some(ref whitelist) = server_lists.whitelist {
+                                                    if player_uuid.is_none() && whitelist.online_mode() {
+                                                        status("Client did not provide a uuid: Checking its name against whitelist");
+                                                    }
+                                                    if whitelist.contains(player_uuid, &name) {
+                                                        status(&format!("\x1b[38;5;14m{name}\x1b[0m is whitelisted"));
                                                     } else {
                                                         codec.send_packet(clientbound::LoginPacket::Disconnect {
                                                             reason: r#"{"text": "You are not whitelisted on this server"}"#.to_owned()
                                                         }).await?;
                                                         status(&format!("\x1b[38;5;14m{name}\x1b[0m is not whitelsited. Disconnected player"));
+                                                        return Ok(Some(rejection("not whitelisted")))
                                                     }
-                                                } else {
-                                                    status("Client did not provide a uuid: Can not check against whitelist");
+                                                }
+
+                                                if let Err(refusal) = limiter.admit_start_request(client_ip, &name) {
                                                     codec.send_packet(clientbound::LoginPacket::Disconnect {
-                                                        reason: r#"{"text": "You are not whitelisted on this server"}"#.to_owned()
+                                                        reason: text_component(&refusal.message)
                                                     }).await?;
+                                                    status(&format!("Refused start request: {}. Disconnected player", refusal.reason));
+                                                    return Ok(Some(rejection(refusal.reason)))
                                                 }
-                                            } else {
-                                                codec.send_packet(clientbound::LoginPacket::Disconnect { reason: String::from(LOGIN_RESPONSE) }).await?;
+
+                                                codec.send_packet(clientbound::LoginPacket::Disconnect {
+                                                    reason: String::from(if frozen { RESUME_RESPONSE } else { LOGIN_RESPONSE })
+                                                }).await?;
                                                 status("Disconnected player");
-                                                break Ok(true)
-                                            }
-                                        },
-                                    }},
-                                    other => break Err(io::Error::new(
-                                        io::ErrorKind::Other,
-                                        format!("got an unsupported packet: {other:?}")
-                                    ))
-                                },
-                                other => break Err(io::Error::new(
-                                    io::ErrorKind::InvalidData,
-                                    format!("unsupported protocol version: {}", Option::<ProtocolVersion>::from(other).expect("we already matched against generic packets"))
-                                )),
-                            }}}.await;
+                                                Ok(Some(start_event))
+                                            },
+                                            other => Err(io::Error::other(
+                                                format!("got an unsupported packet: {other:?}")
+                                            ))
+                                        }
+                                    }
+                                }
+                            };
+
+                            let output = if refusal.is_some() {
+                                match tokio::time::timeout_at(accepted_at + REFUSED_CONNECTION_TIMEOUT, output).await {
+                                    Ok(output) => output,
+                                    Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "refused connection took too long")),
+                                }
+                            } else {
+                                output.await
+                            };
 
                             match output {
-                                Ok(should_we_start) => {
+                                Ok(event) => {
                                     println!("{} Closed connection to {address}", Local::now().format(TIME_FORMAT));
-                                    if should_we_start {
-                                        start_sender.send(()).await.expect("channel shouldn't close");
+                                    match event {
+                                        Some(start_event @ Event::StartRequested { .. }) => {
+                                            start_sender.send(start_event).await.expect("channel shouldn't close");
+                                        },
+                                        Some(rejection) => {
+                                            journal.record(rejection.clone()).await;
+                                            webhooks.notify(&rejection);
+                                            hooks.spawn(Hook::WakeRequestRejected, rejection);
+                                        },
+                                        None => {},
                                     }
                                 },
                                 Err(err) => {
@@ -241,57 +659,204 @@ async fn main() {
                             };
                         });
 
-                        false // Don't start the server
+                        None // Don't start the server
                     },
-                    _ = start_reciever.recv() => {
+                    Some(address) = bedrock_reciever.recv() => {
+                        println!("{} \x1b[38;5;14m{address}\x1b[0m → Bedrock client tried to connect", Local::now().format(TIME_FORMAT));
+                        let server_lists = server_lists.borrow().clone();
+                        let refusal = if server_lists.whitelist.is_some() {
+                            Some("its player can't be checked against the whitelist")
+                        } else if server_lists.bans.check(None, "", address.ip()).is_some() {
+                            Some("banned")
+                        } else if schedule.wake_refusal(&Local::now()).is_some() {
+                            Some("refused by the schedule")
+                        } else {
+                            // Bedrock clients are only told apart by their address
+                            limiter.admit_start_request(address.ip(), &address.ip().to_string()).err().map(|refusal| refusal.reason)
+                        };
+                        match refusal {
+                            Some(reason) => {
+                                println!("{} \x1b[38;5;14m{address}\x1b[0m → Refused wake-up: {reason}", Local::now().format(TIME_FORMAT));
+                                let rejection = Event::BedrockWakeRejected { address: address.to_string(), reason: reason.to_owned() };
+                                journal.record(rejection.clone()).await;
+                                webhooks.notify(&rejection);
+                                hooks.spawn(Hook::WakeRequestRejected, rejection);
+                                None
+                            }
+                            None => Some(Event::BedrockStartRequested { address: address.to_string() }),
+                        }
+                    },
+                    start_event = start_reciever.recv() => {
                         // There should always be at least one sender alive.
                         // But just in case, we return anyway if we recieve None
 
-                        true // Start the server
+                        Some(start_event.expect("we hold a sender ourselves")) // Start the server
+                    },
+                    _ = schedule_check.tick(), if schedule.has_force_on() => {
+                        schedule.forced_on(&Local::now()).map(|window| {
+                            println!("\x1b[38;5;14mThe schedule requires the server to be running ({window})\x1b[0m");
+                            Event::ScheduledStart { window: window.to_string() }
+                        })
                     },
-                    line = stdin_reciever.recv() => {
-                        let line = line.expect("channel shouldn't close");
+                    // Disabled once stdin is closed and there is no console pipe
+                    Some(line) = console_reciever.recv() => {
                         if &line == "stop\n" {
-                            std::process::exit(0);
+                            notifier.notify("STOPPING=1");
+                            if frozen {
+                                // It has to be running to handle the stop
+                                println!("\x1b[38;5;14mResuming the minecraft server to stop it\x1b[0m");
+                                let event = Event::ManualStop { command: "stop".to_owned() };
+                                journal.record(event.clone()).await;
+                                hooks.run(Hook::PreStop, &event, &[]).await;
+                                let exit = match backend.resume().await {
+                                    Ok(()) => {
+                                        request_stop(&mut *backend).await;
+                                        Some(backend.wait().await)
+                                    }
+                                    Err(err) => {
+                                        println!("\x1b[38;5;11mWarning: Couldn't resume the minecraft server. Got err: {err}\x1b[0m");
+                                        kill_frozen(&mut *backend).await
+                                    }
+                                };
+                                if let Some(exit) = exit {
+                                    println!("\x1b[38;5;14mMinecraft server exited on status: {exit}\x1b[0m");
+                                    hooks.run(Hook::PostStop, &event, &[("AM_STATUS", exit.status)]).await;
+                                }
+                            }
+                            if let Some(backup) = backup.take() {
+                                println!("\x1b[38;5;14mWaiting for the backup to finish before exiting\x1b[0m");
+                                let _ = backup.await;
+                            }
+                            console::exit(0).await;
                         } else if &line == "start\n" {
-                            true
+                            Some(Event::ConsoleStart)
                         } else {
                             println!("\x1b[38;5;11mUnknown command\x1b[0m");
-                            false
+                            None
                         }
                     }
                 ) {
-                    // We exit the connection-handling loop whenever one of the branches returns true
+                    // We exit the connection-handling loop whenever one of the branches returns an event
                     // and switch to the next state in the main loop (running the server)
+                    if let Some(backup) = backup.take() {
+                        if !backup.is_finished() {
+                            println!("\x1b[38;5;14mWaiting for the backup to finish before starting the minecraft server\x1b[0m");
+                        }
+                        let _ = backup.await;
+                    }
+                    // Resuming doesn't start anything, so there is nothing to prepare
+                    if !frozen && !hooks.pre_start(&start_event).await {
+                        let event = Event::StartAborted {
+                            request: start_event.to_string(),
+                            reason: "a pre-start hook failed".to_owned(),
+                        };
+                        journal.record(event.clone()).await;
+                        webhooks.notify(&event);
+                        // Keep spoofing
+                        continue;
+                    }
+                    // Only once the start is going ahead
+                    journal.record(start_event.clone()).await;
+                    webhooks.notify(&start_event);
+                    limiter.record_start();
                     break;
                 }
             }
+
+            for responder in [query_responder, bedrock_responder].into_iter().flatten() {
+                responder.abort();
+            }
         }
         {
-            println!("\n\x1b[38;2;0;200;0mStarting minecraft server as child process\x1b[0m\n");
-
-            let mut mc_server = Command::new("/bin/bash")
-                .args([args.start_script.as_os_str()])
-                .stdin(Stdio::piped())
-                .spawn()
-                .expect("failed to start server in subprocess");
+            if std::mem::take(&mut already_running) {
+                println!("\n\x1b[38;2;0;200;0mThe minecraft server is already running\x1b[0m\n");
+            } else if std::mem::take(&mut frozen) {
+                println!("\n\x1b[38;2;0;200;0mResuming minecraft server\x1b[0m\n");
+                notifier.status("Resuming the minecraft server");
+                if let Err(err) = backend.resume().await {
+                    println!("\x1b[38;5;11mWarning: Couldn't resume the minecraft server. Got err: {err}\x1b[0m");
+                    let event = Event::Crash {
+                        status: format!("couldn't resume: {err}"),
+                    };
+                    journal.record(event.clone()).await;
+                    webhooks.notify(&event);
+                    hooks.run(Hook::Crash, &event, &[]).await;
+                    // Left frozen, it would hold on to its port and world forever
+                    match kill_frozen(&mut *backend).await {
+                        Some(exit) => println!(
+                            "\x1b[38;5;14mMinecraft server exited on status: {exit}\x1b[0m"
+                        ),
+                        // Still there, so the next wake-up tries resuming it again rather than starting another one
+                        None => frozen = true,
+                    }
+                    continue;
+                }
+            } else {
+                println!("\n\x1b[38;2;0;200;0mStarting minecraft server\x1b[0m\n");
+                notifier.status("Starting the minecraft server");
+                if let Err(err) = backend.start().await {
+                    println!("\x1b[38;5;11mWarning: Couldn't start the minecraft server. Got err: {err}\x1b[0m");
+                    let event = Event::Crash {
+                        status: format!("couldn't start: {err}"),
+                    };
+                    journal.record(event.clone()).await;
+                    webhooks.notify(&event);
+                    hooks.run(Hook::Crash, &event, &[]).await;
+                    continue;
+                }
+            }
 
-            let mut mc_stdin = mc_server
-                .stdin
-                .take()
-                .expect("should have been able to bind to minecraft server stdin");
+            // The minecraft server can't bind sockets we hold on to, so we forward connections to it
+            let proxy = persistent_listeners.clone().map(|listeners| {
+                task::spawn(listen::proxy(
+                    listeners,
+                    probe_address,
+                    args.send_proxy_protocol,
+                ))
+            });
 
-            let mut last_activity = Instant::now();
+            let mut idle_tracker = IdleTracker::new(Instant::now());
+            let mut probe = tokio::time::interval_at(
+                tokio::time::Instant::now() + args.idle.probe_interval(),
+                args.idle.probe_interval(),
+            );
             let mut number_of_nulls: u32 = 0;
+            // Set when we asked the server to stop, to tell stops apart from crashes
+            let mut stop_event: Option<Event> = None;
 
             loop {
                 tokio::select!(
-                    exit_status = mc_server.wait() => {
-                        drop(mc_stdin);
-                        break println!("\x1b[38;5;14mMinecraft server exited on status: {exit_status:?}\x1b[0m");
+                    exit = backend.wait() => {
+                        println!("\x1b[38;5;14mMinecraft server exited on status: {exit}\x1b[0m");
+                        match stop_event.take() {
+                            Some(stop_event) => {
+                                hooks.run(Hook::PostStop, &stop_event, &[("AM_STATUS", exit.status)]).await;
+                            },
+                            None => {
+                                let (hook, event) = if exit.success {
+                                    (Hook::PostStop, Event::Exit { status: exit.status })
+                                } else {
+                                    (Hook::Crash, Event::Crash { status: exit.status })
+                                };
+                                journal.record(event.clone()).await;
+                                webhooks.notify(&event);
+                                hooks.run(hook, &event, &[]).await;
+                            },
+                        }
+                        break;
                     },
-                    _ = tokio::time::sleep(Duration::from_secs(10)) => {
-                        match get_playercount(socket).await {
+                    _ = probe.tick() => {
+                        let playercount = match args.query.player_count_source() {
+                            PlayerCountSource::Status => get_status(probe_address).await.and_then(|status| {
+                                mod_info_sender.send_replace(Arc::new(mod_info_of(&status)));
+                                Ok((playercount(&status)?, None))
+                            }),
+                            PlayerCountSource::Query => query::full_stat(query_address)
+                                .await
+                                .map(|stat| (stat.num_players, Some(stat.players)))
+                                .map_err(PlayercountError::IO),
+                        };
+                        match playercount {
                             Err(err) => match err {
                                 PlayercountError::GotNull => {
                                     number_of_nulls += 1;
@@ -302,102 +867,145 @@ async fn main() {
                                 PlayercountError::Inbound => println!("\x1b[38;5;11mWarning: Could not query player count from minecraft server.\nThis is not your fault, it is responding in an incorrect way\x1b[0m"),
                                 PlayercountError::IO(err) => println!("\x1b[38;5;11mWarning: Could not reach minecraft server to query player count. Got err: {err}\x1b[0m"),
                             },
-                            Ok(playercount) => {
-                                if playercount == 0 && last_activity.elapsed() >= Duration::from_secs(u64::from(args.timeout) * 60) {
-                                    println!("\x1b[38;5;14mStopping Minecraft Server due to inactivity\x1b[0m");
-                                    write_line(&mut mc_stdin, "stop\n").await.expect("should have been able to forward input to minecraft server stdin");
-                                    drop(mc_stdin);
-                                    break println!("\x1b[38;5;14mMinecraft server exited on status: {:?}\x1b[0m", mc_server.wait().await);
-                                } else if playercount != 0 {
-                                    last_activity = Instant::now();
+                            Ok((playercount, players)) => {
+                                let now = Local::now();
+                                notifier.status(&match players {
+                                    Some(players) if !players.is_empty() => format!("Minecraft server running, {playercount} player(s) online: {}", players.join(", ")),
+                                    _ => format!("Minecraft server running, {playercount} player(s) online"),
+                                });
+                                if idle_tracker.observe(playercount, Instant::now()) {
+                                    journal.record(Event::Ready).await;
+                                    webhooks.notify(&Event::Ready);
+                                    hooks.spawn(Hook::PostReady, Event::Ready);
+                                }
+                                if let Some((reason, idle)) = idle_tracker
+                                    .should_stop(&args.idle, schedule.timeout(&now), playercount, Instant::now())
+                                    .filter(|_| schedule.forced_on(&now).is_none())
+                                {
+                                    let idle_duration = duration::format_duration(Duration::from_secs(idle.as_secs()));
+                                    if args.idle.action() == IdleAction::Freeze {
+                                        println!("\x1b[38;5;14mFreezing Minecraft Server due to inactivity: {}\x1b[0m", idle::describe(reason, idle));
+                                        match backend.freeze().await {
+                                            Ok(()) => {
+                                                let event = Event::IdleFreeze {
+                                                    reason: reason.to_string(),
+                                                    idle: idle_duration,
+                                                };
+                                                journal.record(event.clone()).await;
+                                                webhooks.notify(&event);
+                                                frozen = true;
+                                                break;
+                                            }
+                                            Err(err) => println!("\x1b[38;5;11mWarning: Couldn't freeze the minecraft server, stopping it instead. Got err: {err}\x1b[0m"),
+                                        }
+                                    }
+                                    println!("\x1b[38;5;14mStopping Minecraft Server due to inactivity: {}\x1b[0m", idle::describe(reason, idle));
+                                    notifier.status("Stopping the minecraft server");
+                                    let event = Event::IdleStop {
+                                        reason: reason.to_string(),
+                                        idle: idle_duration,
+                                    };
+                                    journal.record(event.clone()).await;
+                                    webhooks.notify(&event);
+                                    hooks.run(Hook::PreStop, &event, &[]).await;
+                                    request_stop(&mut *backend).await;
+                                    let exit = backend.wait().await;
+                                    println!("\x1b[38;5;14mMinecraft server exited on status: {exit}\x1b[0m");
+                                    hooks.run(Hook::PostStop, &event, &[("AM_STATUS", exit.status)]).await;
+                                    if let Some(ref server_root) = args.server_root {
+                                        backup = args.backup.start(server_root);
+                                    }
+                                    break;
                                 }
                             }
                         }
                     },
-                    line = stdin_reciever.recv() => {
-                        let line = line.expect("channel shouldn't close");
+                    // Disabled once stdin is closed and there is no console pipe
+                    Some(line) = console_reciever.recv() => {
                         if &line == "spoof\n" {
                             println!("\x1b[38;5;14mStopping minecraft server and entering spoofing mode\x1b[0m");
+                            notifier.status("Stopping the minecraft server");
+                            let event = Event::ManualStop { command: "spoof".to_owned() };
+                            journal.record(event.clone()).await;
+                            hooks.run(Hook::PreStop, &event, &[]).await;
+                            stop_event = Some(event);
 
-                            write_line(&mut mc_stdin, "stop\n").await.expect("should have been able to forward input to minecraft server stdin");
+                            request_stop(&mut *backend).await;
                         } else if &line == "stop\n" {
                             println!("\x1b[38;5;14mFully stopping the server\x1b[0m");
+                            notifier.notify("STOPPING=1");
+                            let event = Event::ManualStop { command: "stop".to_owned() };
+                            journal.record(event.clone()).await;
+                            hooks.run(Hook::PreStop, &event, &[]).await;
 
-                            write_line(&mut mc_stdin, "stop\n").await.expect("should have been able to forward input to minecraft server stdin");
+                            request_stop(&mut *backend).await;
 
-                            println!("\x1b[38;5;14mMinecraft server exited on status: {:?}\x1b[0m", mc_server.wait().await);
+                            let exit = backend.wait().await;
+                            println!("\x1b[38;5;14mMinecraft server exited on status: {exit}\x1b[0m");
+                            hooks.run(Hook::PostStop, &event, &[("AM_STATUS", exit.status)]).await;
 
-                            std::process::exit(0);
+                            console::exit(0).await;
                         } else {
-                            write_line(&mut mc_stdin, &line).await.expect("should have been able to forward input to minecraft server stdin")
+                            if let Err(err) = backend.send_command(line.trim_end_matches('\n')).await {
+                                println!("\x1b[38;5;11mWarning: Couldn't forward the command to the minecraft server. Got err: {err}\x1b[0m");
+                            }
                         }
                     },
                 )
             }
+
+            if let Some(proxy) = proxy {
+                proxy.abort();
+            }
         }
     }
 }
 
-enum WhitelistParseError {
-    ParseJson(serde_json::Error),
-    IO(io::Error),
-}
-
-impl From<io::Error> for WhitelistParseError {
-    fn from(err: io::Error) -> Self {
-        WhitelistParseError::IO(err)
+/// Asks the minecraft server to stop, warning when it can't be. `Backend::wait` still returns once it exits
+async fn request_stop(backend: &mut dyn Backend) {
+    if let Err(err) = backend.request_stop().await {
+        println!("\x1b[38;5;11mWarning: Couldn't ask the minecraft server to stop. Got err: {err}\x1b[0m");
     }
 }
 
-async fn parse_whitelist(root_folder: &PathBuf) -> Result<Vec<u128>, WhitelistParseError> {
-    let mut whitelist_path = PathBuf::from(root_folder);
-    whitelist_path.push("whitelist.json");
-
-    let mut ops_path = PathBuf::from(root_folder);
-    ops_path.push("ops.json");
-
-    // let mut ops_file = PathBuf::from(root_folder);
-    // ops_file.push("ops.json");
-
-    let mut whitelist = get_uuids_from_json(&whitelist_path).await?;
-    whitelist.append(&mut get_uuids_from_json(&ops_path).await?);
-
-    whitelist.sort_unstable();
-    whitelist.dedup();
-
-    Ok(whitelist)
+/// Kills a minecraft server that couldn't be resumed, as a stop command would never be handled.
+/// Returns how it exited, or `None` when it couldn't be killed and may still be around
+async fn kill_frozen(backend: &mut dyn Backend) -> Option<Exit> {
+    match backend.kill().await {
+        Ok(()) => Some(backend.wait().await),
+        Err(err) => {
+            println!("\x1b[38;5;11mWarning: Couldn't kill the frozen minecraft server. Got err: {err}\x1b[0m");
+            None
+        }
+    }
 }
 
-async fn get_uuids_from_json(file_path: &Path) -> Result<Vec<u128>, WhitelistParseError> {
-    let mut file_content = String::new();
-    fs::File::open(file_path)
-        .await?
-        .read_to_string(&mut file_content)
-        .await?;
-
-    let objects: Vec<serde_json::Value> = match serde_json::from_str(&file_content) {
-        Ok(parsed) => parsed,
-        Err(err) => return Err(WhitelistParseError::ParseJson(err)),
-    };
-
-    let mut uuids = Vec::<u128>::with_capacity(objects.len());
-
-    for entry in objects {
-        match u128::from_str_radix(&entry["uuid"].to_string().replace(['-', '"'], ""), 16) {
-            Ok(uuid) => uuids.push(uuid),
-            Err(_) => println!(
-                "\x1b[38;5;11mWarning: couldn't parse {} because of this entry:\n{entry}\x1b[0m",
-                file_path.display()
-            ),
-        };
+/// What the query responder tells about the sleeping minecraft server
+fn sleeping_stat(
+    properties: &HashMap<String, String>,
+    interface: IpAddr,
+    port: u16,
+) -> query::Stat {
+    query::Stat {
+        motd: QUERY_MOTD.to_owned(),
+        game_type: "SMP".to_owned(),
+        version: "1.19.2".to_owned(),
+        map: properties
+            .get("level-name")
+            .cloned()
+            .unwrap_or_else(|| "world".to_owned()),
+        max_players: max_players(properties),
+        host_port: port,
+        host_ip: interface.to_string(),
+        ..Default::default()
     }
-
-    Ok(uuids)
 }
 
-async fn write_line(stdin: &mut tokio::process::ChildStdin, line: &str) -> io::Result<()> {
-    stdin.write_all(line.as_bytes()).await?;
-    stdin.flush().await
+fn max_players(properties: &HashMap<String, String>) -> u64 {
+    properties
+        .get("max-players")
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(20)
 }
 
 enum PlayercountError {
@@ -412,30 +1020,36 @@ impl From<io::Error> for PlayercountError {
     }
 }
 
-async fn get_playercount(address: SocketAddrV4) -> Result<u64, PlayercountError> {
+/// Sends a packet to a server, like a client would
+async fn send_serverbound<W, P>(writer: &mut W, packet: P) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin + Send,
+    P: ServerboundPacket + Encode + Send,
+{
+    LengthPrefixed::from_mc_protocol(packet)
+        .await?
+        .serialize_write(writer)
+        .await?;
+    writer.flush().await
+}
+
+async fn get_status(address: SocketAddr) -> Result<serde_json::Value, PlayercountError> {
     let mut stream = TcpStream::connect(address).await?;
     let (read_half, write_half) = stream.split();
     let mut reader = BufReader::new(read_half);
     let mut writer = BufWriter::new(write_half);
 
-    LengthPrefixed::from_mc_protocol(generic_packets::HandshakePacket {
-        protocol_version: McVarint::from(760_i32),
-        server_address: "asd".to_owned(),
-        server_port: 25561,
-        next_state: generic_packets::NextState::Status,
-    })
-    .await?
-    .serialize_write(&mut writer)
+    send_serverbound(
+        &mut writer,
+        generic_packets::HandshakePacket {
+            protocol_version: McVarint::from(760_i32),
+            server_address: "asd".to_owned(),
+            server_port: 25561,
+            next_state: generic_packets::NextState::Status,
+        },
+    )
     .await?;
-
-    writer.flush().await?;
-
-    LengthPrefixed::from_mc_protocol(serverbound::StatusPacket::StatusRequest {})
-        .await?
-        .serialize_write(&mut writer)
-        .await?;
-
-    writer.flush().await?;
+    send_serverbound(&mut writer, serverbound::StatusPacket::StatusRequest {}).await?;
 
     let packet = {
         let mut packet_reader = get_length_prefixed_reader(&mut reader)
@@ -447,11 +1061,114 @@ async fn get_playercount(address: SocketAddrV4) -> Result<u64, PlayercountError>
     };
 
     if let clientbound::StatusPacket::StatusResponse { json_response } = packet {
-        Ok(serde_json::from_str::<serde_json::Value>(&json_response)
-            .map_err(|_| PlayercountError::Inbound)?["players"]["online"]
-            .as_u64()
-            .ok_or(PlayercountError::GotNull)?)
+        serde_json::from_str(&json_response).map_err(|_| PlayercountError::Inbound)
     } else {
         Err(PlayercountError::Inbound)
     }
 }
+
+fn playercount(status: &serde_json::Value) -> Result<u64, PlayercountError> {
+    status["players"]["online"]
+        .as_u64()
+        .ok_or(PlayercountError::GotNull)
+}
+
+/// The fields of a status modded clients check their mods against
+fn mod_info_of(status: &serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
+    MOD_INFO_FIELDS
+        .iter()
+        .filter_map(|&field| Some((field.to_owned(), status.get(field)?.clone())))
+        .collect()
+}
+
+/// Our status, with the mods of the minecraft server so modded clients don't show it as incompatible
+fn status_response(mod_info: &serde_json::Map<String, serde_json::Value>) -> String {
+    if mod_info.is_empty() {
+        return String::from(STATUS_RESPONSE);
+    }
+    let mut response: serde_json::Value =
+        serde_json::from_str(STATUS_RESPONSE).expect("STATUS_RESPONSE should be valid json");
+    response
+        .as_object_mut()
+        .expect("STATUS_RESPONSE should be a json object")
+        .extend(mod_info.clone());
+    response.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(["activitymanager", "./start.sh"].iter().chain(args))
+    }
+
+    #[test]
+    fn probe_interval_test() {
+        assert!(parse(&["--probe-interval", "30s"]).is_ok());
+        // The probe interval ticks would panic on zero
+        let error = parse(&["--probe-interval", "0"]).err().unwrap();
+        assert_eq!(ErrorKind::ValueValidation, error.kind());
+    }
+
+    #[test]
+    fn status_response_test() {
+        // As answered by a 1.19.2 Forge server
+        let status = serde_json::json!({
+            "version": { "name": "1.19.2", "protocol": 760 },
+            "players": { "max": 20, "online": 1 },
+            "description": { "text": "A Minecraft Server" },
+            "forgeData": {
+                "channels": [],
+                "mods": [{ "modId": "forge", "modmarker": "43.2.0" }],
+                "fmlNetworkVersion": 3,
+                "truncated": false,
+                "d": "\u{0300}\u{0}"
+            }
+        });
+        let mod_info = mod_info_of(&status);
+        assert_eq!(vec!["forgeData"], mod_info.keys().collect::<Vec<_>>());
+
+        let response: serde_json::Value =
+            serde_json::from_str(&status_response(&mod_info)).unwrap();
+        let sleeping: serde_json::Value = serde_json::from_str(STATUS_RESPONSE).unwrap();
+        assert_eq!(status["forgeData"], response["forgeData"]);
+        // Everything else is still ours
+        assert_eq!(sleeping["description"], response["description"]);
+        assert_eq!(sleeping["version"], response["version"]);
+        assert!(response.get("players").is_none());
+
+        // Servers before 1.13 describe their mods differently
+        let legacy = serde_json::json!({ "modinfo": { "type": "FML", "modList": [] } });
+        let response: serde_json::Value =
+            serde_json::from_str(&status_response(&mod_info_of(&legacy))).unwrap();
+        assert_eq!(legacy["modinfo"], response["modinfo"]);
+
+        // Vanilla servers have nothing to add
+        let vanilla = serde_json::json!({ "version": { "name": "1.19.2", "protocol": 760 } });
+        assert_eq!(STATUS_RESPONSE, status_response(&mod_info_of(&vanilla)));
+    }
+
+    #[test]
+    fn scrollback_test() {
+        assert_eq!(1000, parse(&[]).unwrap().scrollback.get());
+        // Remote consoles couldn't be sent anything
+        let error = parse(&["--scrollback", "0"]).err().unwrap();
+        assert_eq!(ErrorKind::ValueValidation, error.kind());
+    }
+
+    #[test]
+    fn idle_action_test() {
+        assert!(parse(&["--idle-action", "stop"]).is_ok());
+        // The frozen server keeps listening elsewhere
+        let error = parse(&["--idle-action", "freeze"]).err().unwrap();
+        assert_eq!(ErrorKind::MissingRequiredArgument, error.kind());
+        assert!(parse(&[
+            "--idle-action",
+            "freeze",
+            "--probe-address",
+            "127.0.0.1:25566"
+        ])
+        .is_ok());
+    }
+}
@@ -1,96 +1,352 @@
-use tokio::{
-    io::{self, AsyncWriteExt, BufReader, BufWriter},
-    net::{
-        tcp::{OwnedReadHalf, OwnedWriteHalf},
-        TcpStream,
-    },
-};
+use std::net::SocketAddr;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{io, net::TcpStream};
+use tokio_util::codec::{Decoder, Encoder, Framed};
 
 use crate::mc_protocol::{
-    data_types::{get_length_prefixed_reader, LengthPrefixed},
+    clientbound_packets::v760_packets as clientbound,
+    framed::{ClientHandshake, Forwarding, HandshakeCodec, LoginCodec, StatusCodec},
     serverbound_packets::{
-        self,
-        generic_packets::{is_packet_server_list_ping, NextState},
-        Serverbound,
+        generic_packets::{BungeeForwarding, HandshakePacket, NextState, ServerListPingPacket},
+        v760_packets as serverbound, ServerboundLogin,
     },
-    ConnectionState, McProtocol, ProtocolVersion, ProtocolVersionLevelDeserialize,
+    ProtocolVersion,
 };
 
-pub struct ServerCodec {
-    reader: BufReader<OwnedReadHalf>,
-    writer: BufWriter<OwnedWriteHalf>,
-    connection_state: ConnectionState,
-    protocol_version: Option<ProtocolVersion>,
+/// The server side of a connection, in the state of the codec given by its type parameter:
+/// it starts with a [`HandshakeCodec`], and the handshake moves it to a [`StatusCodec`] or a [`LoginCodec`].
+/// Each state only reads and sends the packets valid in it.
+pub struct ServerCodec<Codec = HandshakeCodec> {
+    framed: Framed<TcpStream, Codec>,
+    proxied_address: Option<SocketAddr>,
+    bungee_forwarding: Option<BungeeForwarding>,
+}
+
+/// Where the handshake of a client leads the connection
+pub enum Handshake {
+    /// Clients older than 1.7 ping with a single packet, after which there is nothing left to read
+    ServerListPing {
+        packet: ServerListPingPacket,
+        proxied_address: Option<SocketAddr>,
+    },
+    Status(HandshakePacket, ServerCodec<StatusCodec>),
+    Login(HandshakePacket, ServerCodec<LoginCodec>),
+}
+
+impl Handshake {
+    /// The address of the client given by the PROXY header
+    pub fn proxied_address(&self) -> Option<SocketAddr> {
+        match self {
+            Self::ServerListPing {
+                proxied_address, ..
+            } => *proxied_address,
+            Self::Status(_, codec) => codec.proxied_address(),
+            Self::Login(_, codec) => codec.proxied_address(),
+        }
+    }
+
+    /// The client information forwarded by BungeeCord
+    pub fn bungee_forwarding(&self) -> Option<&BungeeForwarding> {
+        match self {
+            Self::ServerListPing { .. } => None,
+            Self::Status(_, codec) => codec.bungee_forwarding(),
+            Self::Login(_, codec) => codec.bungee_forwarding(),
+        }
+    }
+}
+
+impl<Codec: Decoder<Error = io::Error>> ServerCodec<Codec> {
+    /// The address of the client given by the PROXY header
+    pub fn proxied_address(&self) -> Option<SocketAddr> {
+        self.proxied_address
+    }
+
+    /// The client information forwarded by BungeeCord
+    pub fn bungee_forwarding(&self) -> Option<&BungeeForwarding> {
+        self.bungee_forwarding.as_ref()
+    }
+
+    async fn read(&mut self) -> io::Result<Codec::Item> {
+        self.framed.next().await.unwrap_or_else(|| {
+            Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the client closed the connection",
+            ))
+        })
+    }
+
+    async fn send<P>(&mut self, packet: P) -> io::Result<()>
+    where
+        Codec: Encoder<P, Error = io::Error>,
+    {
+        self.framed.send(packet).await
+    }
 }
 
-impl ServerCodec {
-    pub fn new(stream: TcpStream) -> Self {
-        let (read_half, write_half) = stream.into_split();
+impl ServerCodec<HandshakeCodec> {
+    pub fn new(stream: TcpStream, forwarding: Forwarding) -> Self {
         ServerCodec {
-            reader: BufReader::new(read_half),
-            writer: BufWriter::new(write_half),
-            connection_state: ConnectionState::Handshaking,
-            protocol_version: None,
+            framed: Framed::new(stream, HandshakeCodec::new(forwarding)),
+            proxied_address: None,
+            bungee_forwarding: None,
         }
     }
 
-    pub async fn read_packet(&mut self) -> io::Result<Serverbound> {
-        if let ConnectionState::Handshaking = self.connection_state {
-            if is_packet_server_list_ping::<io::Result<bool>>(self.reader.get_mut()).await? {
-                return Ok(
-                    Serverbound::Generic(
-                        serverbound_packets::generic_packets::Generic::ServerListPing(
-                            serverbound_packets::generic_packets::ServerListPingPacket::deserialize_read(&mut self.reader).await?
-        )));
-            }
+    /// Reads the first packet of the connection, which decides what the rest of it is about
+    pub async fn read_handshake(mut self) -> io::Result<Handshake> {
+        Ok(match self.read().await? {
+            ClientHandshake::ServerListPing {
+                packet,
+                proxied_address,
+            } => Handshake::ServerListPing {
+                packet,
+                proxied_address,
+            },
+            ClientHandshake::Handshake {
+                packet,
+                protocol_version,
+                proxied_address,
+                bungee_forwarding,
+            } => match packet.next_state {
+                NextState::Status => {
+                    let codec = ServerCodec {
+                        framed: StatusCodec::after_handshake(self.framed, protocol_version),
+                        proxied_address,
+                        bungee_forwarding,
+                    };
+                    Handshake::Status(packet, codec)
+                }
+                NextState::Login => {
+                    let codec = ServerCodec {
+                        framed: LoginCodec::after_handshake(self.framed, protocol_version),
+                        proxied_address,
+                        bungee_forwarding,
+                    };
+                    Handshake::Login(packet, codec)
+                }
+            },
+        })
+    }
+}
+
+impl ServerCodec<StatusCodec> {
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.framed.codec().protocol_version()
+    }
+
+    pub async fn read_packet(&mut self) -> io::Result<serverbound::StatusPacket> {
+        self.read().await
+    }
+
+    pub async fn send_packet(&mut self, packet: clientbound::StatusPacket) -> io::Result<()> {
+        self.send(packet).await
+    }
+}
+
+impl ServerCodec<LoginCodec> {
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.framed.codec().protocol_version()
+    }
+
+    pub async fn read_packet(&mut self) -> io::Result<ServerboundLogin> {
+        self.read().await
+    }
+
+    pub async fn send_packet(&mut self, packet: clientbound::LoginPacket) -> io::Result<()> {
+        self.send(packet).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{tcp::OwnedReadHalf, TcpListener},
+    };
+
+    use super::*;
+    use crate::mc_protocol::{
+        data_types::{get_length_prefixed_reader, LengthPrefixed, McVarint},
+        proxy_protocol,
+        serverbound_packets::v760_packets::LoginPacket,
+        Decode, Encode,
+    };
+
+    /// A connected client stream and the server codec of its connection
+    async fn connect(forwarding: Forwarding) -> (TcpStream, ServerCodec) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        (client, ServerCodec::new(stream, forwarding))
+    }
+
+    /// Frames packets like clients do, through the async encoding rather than the codecs
+    async fn frame(packet: impl Encode) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        LengthPrefixed::from_mc_protocol(packet)
+            .await
+            .unwrap()
+            .serialize_write(&mut bytes)
+            .await
+            .unwrap();
+        bytes
+    }
+
+    fn handshake(server_address: &str, next_state: NextState) -> HandshakePacket {
+        HandshakePacket {
+            protocol_version: McVarint::from(760_i32),
+            server_address: server_address.to_owned(),
+            server_port: 25565,
+            next_state,
+        }
+    }
+
+    async fn read<P: Decode>(reader: &mut OwnedReadHalf) -> P {
+        let mut packet_reader = get_length_prefixed_reader(reader).await.unwrap();
+        P::deserialize_read(&mut packet_reader).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn status_test() {
+        let (client, codec) = connect(Forwarding {
+            proxy_protocol: true,
+            bungeecord: true,
+        })
+        .await;
+        let player: SocketAddr = "192.0.2.1:56324".parse().unwrap();
+        let (mut client_reader, mut client_writer) = client.into_split();
+
+        // All at once, so the status packets are read along with the handshake
+        let mut bytes = proxy_protocol::v2_header(player, "127.0.0.1:25565".parse().unwrap());
+        bytes.extend(frame(handshake("localhost", NextState::Status)).await);
+        bytes.extend(frame(serverbound::StatusPacket::StatusRequest {}).await);
+        bytes.extend(frame(serverbound::StatusPacket::PingRequest { payload: 42 }).await);
+        client_writer.write_all(&bytes).await.unwrap();
+
+        let handshake = codec.read_handshake().await.unwrap();
+        assert_eq!(Some(player), handshake.proxied_address());
+        let Handshake::Status(packet, mut codec) = handshake else {
+            panic!("expected a status handshake");
         };
+        assert_eq!("localhost", packet.hostname());
+        assert_eq!(ProtocolVersion::V760, codec.protocol_version());
+        // BungeeCord doesn't forward anything when pinging servers
+        assert!(codec.bungee_forwarding().is_none());
 
-        // This will only read a single packet
-        let mut packet_reader = get_length_prefixed_reader(&mut self.reader).await?;
+        assert!(matches!(
+            codec.read_packet().await.unwrap(),
+            serverbound::StatusPacket::StatusRequest {}
+        ));
+        codec
+            .send_packet(clientbound::StatusPacket::StatusResponse {
+                json_response: "{}".to_owned(),
+            })
+            .await
+            .unwrap();
+        assert!(matches!(
+            read(&mut client_reader).await,
+            clientbound::StatusPacket::StatusResponse { json_response } if json_response == "{}"
+        ));
 
-        let packet = if let ConnectionState::Handshaking = self.connection_state {
-            let packet = serverbound_packets::generic_packets::HandshakePacket::deserialize_read(
-                &mut packet_reader,
-            )
-            .await?;
+        assert!(matches!(
+            codec.read_packet().await.unwrap(),
+            serverbound::StatusPacket::PingRequest { payload: 42 }
+        ));
+        codec
+            .send_packet(clientbound::StatusPacket::PingResponse { payload: 42 })
+            .await
+            .unwrap();
+        assert!(matches!(
+            read(&mut client_reader).await,
+            clientbound::StatusPacket::PingResponse { payload: 42 }
+        ));
 
-            self.protocol_version = Some(i32::from(packet.protocol_version.clone()).try_into()?);
+        drop(client_writer);
+        assert_eq!(
+            io::ErrorKind::UnexpectedEof,
+            codec.read_packet().await.unwrap_err().kind()
+        );
+    }
 
-            self.connection_state = match packet.next_state {
-                NextState::Status => ConnectionState::Status,
-                NextState::Login => ConnectionState::Login,
-            };
+    #[tokio::test]
+    async fn login_test() {
+        let (mut client, codec) = connect(Forwarding {
+            proxy_protocol: false,
+            bungeecord: true,
+        })
+        .await;
 
-            Serverbound::Generic(serverbound_packets::generic_packets::Generic::Handshake(
-                packet,
-            ))
-        } else {
-            Serverbound::deserialize_read(
-                &mut packet_reader,
-                self.connection_state,
-                self.protocol_version
-                    .expect("protocol version should be known by this point"),
-            )
-            .await?
+        let forwarded = "localhost\x00203.0.113.7\x00069a79f444e94726a5befca90e38aaf5";
+        client
+            .write_all(&frame(handshake(forwarded, NextState::Login)).await)
+            .await
+            .unwrap();
+        let handshake = codec.read_handshake().await.unwrap();
+        assert_eq!(None, handshake.proxied_address());
+        assert_eq!(
+            Some(0x069a79f444e94726a5befca90e38aaf5),
+            handshake
+                .bungee_forwarding()
+                .map(|forwarding| forwarding.uuid)
+        );
+        let Handshake::Login(_, mut codec) = handshake else {
+            panic!("expected a login handshake");
         };
 
-        let remaining_bytes = packet_reader.limit();
-        if remaining_bytes != 0 {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("{remaining_bytes} bytes were not consumed by the implementation of deserialize_read")
-            ));
+        // Login start of Notch, without signature data
+        let mut login_start = b"\x00\x05Notch\x00\x01".to_vec();
+        login_start.extend(0x069a79f444e94726a5befca90e38aaf5_u128.to_be_bytes());
+        let mut bytes = Vec::new();
+        LengthPrefixed::from(login_start)
+            .serialize_buf(&mut bytes)
+            .unwrap();
+        client.write_all(&bytes).await.unwrap();
+        match codec.read_packet().await.unwrap() {
+            ServerboundLogin::V760(LoginPacket::LoginStart {
+                name, player_uuid, ..
+            }) => {
+                assert_eq!("Notch", name);
+                assert_eq!(Some(0x069a79f444e94726a5befca90e38aaf5), player_uuid);
+            }
+            other => panic!("expected a login start, got {other:?}"),
         }
 
-        Ok(packet)
+        codec
+            .send_packet(clientbound::LoginPacket::Disconnect {
+                reason: "\"The server is starting\"".to_owned(),
+            })
+            .await
+            .unwrap();
+        let (mut client_reader, _client_writer) = client.into_split();
+        let mut disconnect = Vec::new();
+        get_length_prefixed_reader(&mut client_reader)
+            .await
+            .unwrap()
+            .read_to_end(&mut disconnect)
+            .await
+            .unwrap();
+        assert_eq!(&b"\x00\x18\"The server is starting\""[..], &disconnect[..]);
     }
 
-    pub async fn send_packet(&mut self, packet: impl McProtocol) -> io::Result<()> {
-        LengthPrefixed::from_mc_protocol(packet)
-            .await?
-            .serialize_write(&mut self.writer)
-            .await?;
+    #[tokio::test]
+    async fn refused_login_test() {
+        let (mut client, codec) = connect(Forwarding {
+            proxy_protocol: false,
+            bungeecord: true,
+        })
+        .await;
 
-        self.writer.flush().await
+        // Straight to the server, bypassing BungeeCord
+        client
+            .write_all(&frame(handshake("localhost", NextState::Login)).await)
+            .await
+            .unwrap();
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            codec.read_handshake().await.err().unwrap().kind()
+        );
     }
 }
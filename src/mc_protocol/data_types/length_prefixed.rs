@@ -1,17 +1,14 @@
-use tokio::io::{self, AsyncReadExt, AsyncWriteExt, BufWriter, Take};
+use bytes::{Buf, BufMut};
+use tokio::io::{self, AsyncReadExt, Take};
 
-use crate::mc_protocol::{data_types::McVarint, McProtocol};
+use crate::mc_protocol::{data_types::McVarint, Decode, Encode};
 
 pub struct LengthPrefixed {
     data: Vec<u8>,
 }
 
-#[async_trait::async_trait]
-impl McProtocol for LengthPrefixed {
-    async fn serialize_write<W>(&self, writer: &mut W) -> io::Result<()>
-    where
-        W: io::AsyncWrite + Unpin + Send,
-    {
+impl Encode for LengthPrefixed {
+    fn serialize_buf<B: BufMut>(&self, buf: &mut B) -> io::Result<()> {
         McVarint::from(match i32::try_from(self.data.len()) {
             Ok(value) => value,
             Err(_) => {
@@ -21,17 +18,18 @@ impl McProtocol for LengthPrefixed {
                 ))
             }
         })
-        .serialize_write(writer)
-        .await?;
+        .serialize_buf(buf)?;
 
-        writer.write_all(&self.data).await?;
+        buf.put_slice(&self.data);
 
         Ok(())
     }
+}
 
+#[async_trait::async_trait]
+impl Decode for LengthPrefixed {
     async fn deserialize_read<R>(reader: &mut R) -> io::Result<Self>
     where
-        Self: std::marker::Sized,
         R: io::AsyncRead + Unpin + Send,
     {
         let length: u32 = McVarint::deserialize_read(reader).await?.try_into()?;
@@ -52,6 +50,23 @@ impl McProtocol for LengthPrefixed {
 
         Ok(Self { data })
     }
+
+    fn deserialize_buf<B: Buf>(buf: &mut B) -> io::Result<Self> {
+        let length: u32 = McVarint::deserialize_buf(buf)?.try_into()?;
+        let length =
+            usize::try_from(length).expect("u32 should always be within the bounds of usize");
+
+        if buf.remaining() < length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "couldn't read all the bytes from length-prefixed value",
+            ));
+        }
+
+        Ok(Self {
+            data: buf.copy_to_bytes(length).into(),
+        })
+    }
 }
 
 impl From<Vec<u8>> for LengthPrefixed {
@@ -67,13 +82,9 @@ impl From<LengthPrefixed> for Vec<u8> {
 }
 
 impl LengthPrefixed {
-    pub async fn from_mc_protocol(object: impl McProtocol) -> io::Result<Self> {
-        let bytes = {
-            let mut writer = BufWriter::new(Vec::<u8>::new());
-            object.serialize_write(&mut writer).await?;
-            writer.flush().await?;
-            writer.into_inner()
-        };
+    pub async fn from_mc_protocol(object: impl Encode) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        object.serialize_buf(&mut bytes)?;
         Ok(Self::from(bytes))
     }
 }
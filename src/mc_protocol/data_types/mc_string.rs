@@ -1,53 +1,43 @@
-use crate::mc_protocol::{data_types::LengthPrefixed, McProtocol};
+use crate::mc_protocol::{data_types::LengthPrefixed, Decode, Encode};
 
+use bytes::{Buf, BufMut};
 use tokio::io;
 
 use std::marker::{Send, Unpin};
 
-#[async_trait::async_trait]
-impl McProtocol for &str {
-    async fn serialize_write<W>(&self, writer: &mut W) -> io::Result<()>
-    where
-        W: io::AsyncWrite + Unpin + Send,
-    {
-        LengthPrefixed::from(Vec::from(self.as_bytes()))
-            .serialize_write(writer)
-            .await
+/// Only encoded, as decoding a borrowed string would need somewhere to borrow it from
+impl Encode for &str {
+    fn serialize_buf<B: BufMut>(&self, buf: &mut B) -> io::Result<()> {
+        LengthPrefixed::from(Vec::from(self.as_bytes())).serialize_buf(buf)
     }
+}
 
-    async fn deserialize_read<R>(_: &mut R) -> io::Result<Self>
-    where
-        Self: std::marker::Sized,
-        R: io::AsyncRead + Unpin + Send,
-    {
-        unimplemented!()
+impl Encode for String {
+    fn serialize_buf<B: BufMut>(&self, buf: &mut B) -> io::Result<()> {
+        self.as_str().serialize_buf(buf)
     }
 }
 
-#[async_trait::async_trait]
-impl McProtocol for String {
-    async fn serialize_write<W>(&self, writer: &mut W) -> io::Result<()>
-    where
-        W: io::AsyncWrite + Unpin + Send,
-    {
-        LengthPrefixed::from(Vec::from(self.as_bytes()))
-            .serialize_write(writer)
-            .await
+fn string_from(bytes: LengthPrefixed) -> io::Result<String> {
+    match String::from_utf8(Vec::<u8>::from(bytes)) {
+        Ok(string) => Ok(string),
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "String wasn't valid UTF-8",
+        )),
     }
+}
 
+#[async_trait::async_trait]
+impl Decode for String {
     async fn deserialize_read<R>(reader: &mut R) -> io::Result<Self>
     where
-        Self: std::marker::Sized,
         R: io::AsyncRead + Unpin + Send,
     {
-        match String::from_utf8(Vec::<u8>::from(
-            LengthPrefixed::deserialize_read(reader).await?,
-        )) {
-            Ok(string) => Ok(string),
-            Err(_) => Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "String wasn't valid UTF-8",
-            )),
-        }
+        string_from(LengthPrefixed::deserialize_read(reader).await?)
+    }
+
+    fn deserialize_buf<B: Buf>(buf: &mut B) -> io::Result<Self> {
+        string_from(LengthPrefixed::deserialize_buf(buf)?)
     }
 }
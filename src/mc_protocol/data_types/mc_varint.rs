@@ -1,41 +1,44 @@
-use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+use bytes::{Buf, BufMut};
+use tokio::io::{self, AsyncReadExt};
 
-use crate::mc_protocol::McProtocol;
+use crate::mc_protocol::{end_of_data, Decode, Encode};
 
 #[derive(Clone)]
 pub struct McVarint(Vec<u8>);
 
-#[async_trait::async_trait]
-impl McProtocol for McVarint {
-    async fn serialize_write<W>(&self, writer: &mut W) -> io::Result<()>
-    where
-        W: io::AsyncWrite + Unpin + Send,
-    {
-        writer.write_all(&self.0).await?;
+impl Encode for McVarint {
+    fn serialize_buf<B: BufMut>(&self, buf: &mut B) -> io::Result<()> {
+        buf.put_slice(&self.0);
         Ok(())
     }
+}
+
+/// Adds a byte read to the varint, and tells whether it was the last one
+fn push_byte(bytes: &mut Vec<u8>, byte: u8) -> io::Result<bool> {
+    bytes.push(byte);
+    if bytes.len() > 5 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "varints can't be over 5 bytes long",
+        ));
+    }
+    Ok(byte < 128)
+}
 
+#[async_trait::async_trait]
+impl Decode for McVarint {
     async fn deserialize_read<R>(reader: &mut R) -> io::Result<Self>
     where
-        Self: std::marker::Sized,
         R: io::AsyncRead + Unpin + Send,
     {
         let mut bytes = Vec::<u8>::new();
+        while !push_byte(&mut bytes, reader.read_u8().await?)? {}
+        Ok(McVarint(bytes))
+    }
 
-        loop {
-            let byte = reader.read_u8().await?;
-            bytes.push(byte);
-            if bytes.len() > 5 {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "varints can't be over 5 bytes long",
-                ));
-            }
-            if byte < 128 {
-                break;
-            }
-        }
-
+    fn deserialize_buf<B: Buf>(buf: &mut B) -> io::Result<Self> {
+        let mut bytes = Vec::<u8>::new();
+        while !push_byte(&mut bytes, buf.try_get_u8().map_err(end_of_data)?)? {}
         Ok(McVarint(bytes))
     }
 }
@@ -48,7 +51,8 @@ impl From<i32> for McVarint {
 
         // Create a vector of the right size
         let number_of_bits = 32 - value.leading_zeros() as usize;
-        let number_of_bytes: usize = (number_of_bits / 7) + (number_of_bits % 7 > 0) as usize;
+        let number_of_bytes: usize =
+            (number_of_bits / 7) + !number_of_bits.is_multiple_of(7) as usize;
         let mut bytes = vec![0_u8; number_of_bytes];
 
         // Make groups of 7 bits
@@ -118,8 +122,7 @@ impl TryFrom<u32> for McVarint {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::mc_protocol::McProtocol;
-    use tokio::io::{BufReader, BufWriter};
+    use tokio::io::{AsyncWriteExt, BufReader, BufWriter};
 
     #[tokio::test]
     async fn mc_varint_create_test() {
@@ -146,6 +149,24 @@ mod tests {
         assert_eq!(500, number);
     }
 
+    #[test]
+    fn mc_varint_buf_test() {
+        let mut bytes = Vec::new();
+        McVarint::from(-1).serialize_buf(&mut bytes).unwrap();
+        assert_eq!(vec![0xff, 0xff, 0xff, 0xff, 0x0f], bytes);
+
+        let mut data = &b"\xf4\x03\x2a"[..];
+        assert_eq!(
+            500,
+            i32::from(McVarint::deserialize_buf(&mut data).unwrap())
+        );
+        assert_eq!(b"\x2a", data);
+
+        let error = McVarint::deserialize_buf(&mut &b"\xf4"[..]).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, error.kind());
+        assert!(McVarint::deserialize_buf(&mut &[0xff; 6][..]).is_err());
+    }
+
     #[tokio::test]
     async fn mc_varint_null() {
         let varint = McVarint::from(0);
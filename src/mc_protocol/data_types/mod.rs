@@ -1,4 +1,5 @@
 pub mod mc_string;
+mod primitives;
 
 mod mc_varint;
 pub use mc_varint::McVarint;
@@ -0,0 +1,78 @@
+use bytes::{Buf, BufMut};
+use tokio::io::{self, AsyncReadExt};
+
+use crate::mc_protocol::{end_of_data, Decode, Encode};
+
+/// Fixed size numbers are sent big-endian
+macro_rules! impl_primitive {
+    ($($ty:ty: $put:ident, $read:ident, $get:ident;)*) => {
+        $(
+            impl Encode for $ty {
+                fn serialize_buf<B: BufMut>(&self, buf: &mut B) -> io::Result<()> {
+                    buf.$put(*self);
+                    Ok(())
+                }
+            }
+
+            #[async_trait::async_trait]
+            impl Decode for $ty {
+                async fn deserialize_read<R>(reader: &mut R) -> io::Result<Self>
+                where
+                    R: io::AsyncRead + Unpin + Send,
+                {
+                    reader.$read().await
+                }
+
+                fn deserialize_buf<B: Buf>(buf: &mut B) -> io::Result<Self> {
+                    buf.$get().map_err(end_of_data)
+                }
+            }
+        )*
+    };
+}
+
+impl_primitive! {
+    u8: put_u8, read_u8, try_get_u8;
+    i8: put_i8, read_i8, try_get_i8;
+    u16: put_u16, read_u16, try_get_u16;
+    i16: put_i16, read_i16, try_get_i16;
+    u32: put_u32, read_u32, try_get_u32;
+    i32: put_i32, read_i32, try_get_i32;
+    u64: put_u64, read_u64, try_get_u64;
+    i64: put_i64, read_i64, try_get_i64;
+    u128: put_u128, read_u128, try_get_u128;
+    f32: put_f32, read_f32, try_get_f32;
+    f64: put_f64, read_f64, try_get_f64;
+}
+
+impl Encode for bool {
+    fn serialize_buf<B: BufMut>(&self, buf: &mut B) -> io::Result<()> {
+        buf.put_u8(u8::from(*self));
+        Ok(())
+    }
+}
+
+fn bool_from(byte: u8) -> io::Result<bool> {
+    match byte {
+        0 => Ok(false),
+        1 => Ok(true),
+        other => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected boolean variant: {other}"),
+        )),
+    }
+}
+
+#[async_trait::async_trait]
+impl Decode for bool {
+    async fn deserialize_read<R>(reader: &mut R) -> io::Result<Self>
+    where
+        R: io::AsyncRead + Unpin + Send,
+    {
+        bool_from(reader.read_u8().await?)
+    }
+
+    fn deserialize_buf<B: Buf>(buf: &mut B) -> io::Result<Self> {
+        bool_from(buf.try_get_u8().map_err(end_of_data)?)
+    }
+}
@@ -0,0 +1,224 @@
+//! What the code generated by `#[derive(Encode, Decode)]` calls. Not meant to be used directly
+
+pub use async_trait::async_trait;
+pub use bytes::{Buf, BufMut};
+pub use tokio::io;
+
+use tokio::io::AsyncReadExt;
+
+use crate::mc_protocol::{
+    data_types::{LengthPrefixed, McVarint},
+    end_of_data, Decode, Encode,
+};
+
+pub fn put_id<B: BufMut>(buf: &mut B, id: u8) -> io::Result<()> {
+    buf.put_u8(id);
+    Ok(())
+}
+
+pub async fn read_id<R>(reader: &mut R) -> io::Result<u8>
+where
+    R: io::AsyncRead + Unpin + Send,
+{
+    reader.read_u8().await
+}
+
+pub fn get_id<B: Buf>(buf: &mut B) -> io::Result<u8> {
+    buf.try_get_u8().map_err(end_of_data)
+}
+
+fn check_id(type_name: &str, actual: u8, id: u8) -> io::Result<()> {
+    match actual {
+        actual if actual == id => Ok(()),
+        other => Err(unexpected_id(type_name, other)),
+    }
+}
+
+pub async fn expect_id<R>(reader: &mut R, id: u8, type_name: &str) -> io::Result<()>
+where
+    R: io::AsyncRead + Unpin + Send,
+{
+    check_id(type_name, read_id(reader).await?, id)
+}
+
+pub fn expect_id_buf<B: Buf>(buf: &mut B, id: u8, type_name: &str) -> io::Result<()> {
+    check_id(type_name, get_id(buf)?, id)
+}
+
+pub fn unexpected_id(type_name: &str, id: u8) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected {type_name} ID: {id}"),
+    )
+}
+
+pub fn unsupported(name: &str) -> io::Error {
+    io::Error::other(format!("Deserializing {name} is not supported"))
+}
+
+pub fn check_length(field: &str, length: usize, max: usize) -> io::Result<()> {
+    if length > max {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{field} can't be over {max} bytes long"),
+        ));
+    }
+    Ok(())
+}
+
+pub fn put_bool<B: BufMut>(buf: &mut B, value: bool) -> io::Result<()> {
+    value.serialize_buf(buf)
+}
+
+pub async fn read_bool<R>(reader: &mut R) -> io::Result<bool>
+where
+    R: io::AsyncRead + Unpin + Send,
+{
+    bool::deserialize_read(reader).await
+}
+
+pub fn get_bool<B: Buf>(buf: &mut B) -> io::Result<bool> {
+    bool::deserialize_buf(buf)
+}
+
+pub fn put_varint<B: BufMut>(buf: &mut B, value: i32) -> io::Result<()> {
+    McVarint::from(value).serialize_buf(buf)
+}
+
+pub async fn read_varint<R>(reader: &mut R) -> io::Result<i32>
+where
+    R: io::AsyncRead + Unpin + Send,
+{
+    Ok(McVarint::deserialize_read(reader).await?.into())
+}
+
+pub fn get_varint<B: Buf>(buf: &mut B) -> io::Result<i32> {
+    Ok(McVarint::deserialize_buf(buf)?.into())
+}
+
+pub fn put_bytes<B: BufMut>(buf: &mut B, bytes: &[u8]) -> io::Result<()> {
+    LengthPrefixed::from(bytes.to_vec()).serialize_buf(buf)
+}
+
+pub async fn read_bytes<R>(reader: &mut R) -> io::Result<Vec<u8>>
+where
+    R: io::AsyncRead + Unpin + Send,
+{
+    Ok(LengthPrefixed::deserialize_read(reader).await?.into())
+}
+
+pub fn get_bytes<B: Buf>(buf: &mut B) -> io::Result<Vec<u8>> {
+    Ok(LengthPrefixed::deserialize_buf(buf)?.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+
+    use super::io;
+    use crate::mc_protocol::{Decode, Encode};
+
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    #[mc(id = 3)]
+    struct Fixture {
+        #[mc(varint)]
+        count: i32,
+        #[mc(max_length = 4)]
+        name: String,
+        #[mc(length_prefixed)]
+        data: Vec<u8>,
+        #[mc(optional, varint)]
+        maybe: Option<i32>,
+        #[mc(optional, length_prefixed, max_length = 2)]
+        extra: Option<Vec<u8>>,
+    }
+
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    #[mc(unsupported(teleport = 5))]
+    enum FixtureEnum {
+        #[mc(id = 1)]
+        Empty,
+        #[mc(id = 2)]
+        Value { value: u16 },
+    }
+
+    /// Checks `value` encodes to exactly `bytes`, and that both ways of decoding read it back from them
+    async fn round_trip<T: Encode + Decode + PartialEq + Debug>(value: T, bytes: &[u8]) {
+        let mut encoded = Vec::new();
+        value.serialize_buf(&mut encoded).unwrap();
+        assert_eq!(bytes, &encoded[..]);
+
+        let mut buf = bytes;
+        assert_eq!(value, T::deserialize_buf(&mut buf).unwrap());
+        assert!(buf.is_empty());
+        let mut reader = bytes;
+        assert_eq!(value, T::deserialize_read(&mut reader).await.unwrap());
+        assert!(reader.is_empty());
+    }
+
+    #[tokio::test]
+    async fn struct_test() {
+        round_trip(
+            Fixture {
+                count: 300,
+                name: "abcd".to_owned(),
+                data: vec![7, 8],
+                maybe: Some(-1),
+                extra: Some(vec![9]),
+            },
+            b"\x03\xac\x02\x04abcd\x02\x07\x08\x01\xff\xff\xff\xff\x0f\x01\x01\x09",
+        )
+        .await;
+        round_trip(
+            Fixture {
+                count: 0,
+                name: String::new(),
+                data: Vec::new(),
+                maybe: None,
+                extra: None,
+            },
+            b"\x03\x00\x00\x00\x00\x00",
+        )
+        .await;
+
+        // A wrong ID
+        assert!(Fixture::deserialize_buf(&mut &b"\x04\x00\x00\x00\x00\x00"[..]).is_err());
+    }
+
+    #[tokio::test]
+    async fn max_length_test() {
+        let too_long = Fixture {
+            count: 0,
+            name: "abcde".to_owned(),
+            data: Vec::new(),
+            maybe: None,
+            extra: None,
+        };
+        assert!(too_long.serialize_buf(&mut Vec::new()).is_err());
+        assert!(Fixture::deserialize_buf(&mut &b"\x03\x00\x05abcde\x00\x00\x00"[..]).is_err());
+
+        // Inside an option too
+        let too_long = Fixture {
+            extra: Some(vec![1, 2, 3]),
+            name: String::new(),
+            ..too_long
+        };
+        assert!(too_long.serialize_buf(&mut Vec::new()).is_err());
+        let bytes = b"\x03\x00\x00\x00\x00\x01\x03\x01\x02\x03";
+        assert!(Fixture::deserialize_buf(&mut &bytes[..]).is_err());
+        assert!(Fixture::deserialize_read(&mut &bytes[..]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn enum_test() {
+        round_trip(FixtureEnum::Empty, b"\x01").await;
+        round_trip(FixtureEnum::Value { value: 0x1234 }, b"\x02\x12\x34").await;
+
+        let unsupported = FixtureEnum::deserialize_buf(&mut &b"\x05"[..]).unwrap_err();
+        assert!(unsupported.to_string().contains("teleport"));
+        let unexpected = FixtureEnum::deserialize_read(&mut &b"\x06"[..])
+            .await
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, unexpected.kind());
+    }
+}
@@ -0,0 +1,422 @@
+use std::net::SocketAddr;
+
+use bytes::{Buf, BytesMut};
+use tokio::io;
+use tokio_util::codec::{Decoder, Encoder, Framed, FramedParts};
+
+use crate::mc_protocol::{
+    clientbound_packets::v760_packets as clientbound,
+    data_types::{LengthPrefixed, McVarint},
+    proxy_protocol,
+    serverbound_packets::{
+        generic_packets::{
+            server_list_ping_length, starts_server_list_ping, BungeeForwarding, HandshakePacket,
+            NextState, ServerListPingPacket, SERVER_LIST_PING_HEADER_LENGTH,
+        },
+        v760_packets as serverbound, ServerboundLogin,
+    },
+    ClientboundPacket, Decode, Encode, ProtocolVersion, ServerboundPacket,
+};
+
+/// Longest packet the protocol allows, as its length prefix can't be over 3 bytes long
+const MAX_PACKET_LENGTH: usize = 2_097_151;
+
+/// How proxies in front of us tell who connections come from.
+/// Only enable what the proxies actually send, as clients could otherwise pretend to be anyone.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Forwarding {
+    /// Connections start with a PROXY protocol header
+    pub proxy_protocol: bool,
+    /// Logins come with the address and uuid of the player in their handshake
+    pub bungeecord: bool,
+}
+
+/// The first packet of a connection, with where the client comes from
+#[derive(Debug)]
+pub enum ClientHandshake {
+    /// Clients older than 1.7 ping with a single packet, after which there is nothing left to read
+    ServerListPing {
+        packet: ServerListPingPacket,
+        proxied_address: Option<SocketAddr>,
+    },
+    Handshake {
+        packet: HandshakePacket,
+        protocol_version: ProtocolVersion,
+        proxied_address: Option<SocketAddr>,
+        bungee_forwarding: Option<BungeeForwarding>,
+    },
+}
+
+/// Decodes the start of a connection for `tokio_util::codec::Framed`: the PROXY header if there is one,
+/// then the handshake. Clients don't get anything in this state.
+///
+/// The handshake decides the codec of the rest of the connection,
+/// [`StatusCodec`] or [`LoginCodec`], which carry on from what was already read.
+#[derive(Debug)]
+pub struct HandshakeCodec {
+    forwarding: Forwarding,
+    header_read: bool,
+    proxied_address: Option<SocketAddr>,
+}
+
+/// Decodes the status requests of a client and encodes the answers
+#[derive(Debug)]
+pub struct StatusCodec {
+    protocol_version: ProtocolVersion,
+}
+
+/// Decodes the login packets of a client and encodes the answers
+#[derive(Debug)]
+pub struct LoginCodec {
+    protocol_version: ProtocolVersion,
+}
+
+/// Splits the next packet off the buffer, without its length prefix, or returns None if it isn't all there yet
+fn split_frame(src: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+    let mut data = &src[..];
+    let length = match McVarint::deserialize_buf(&mut data) {
+        Ok(length) => length,
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let prefix_length = src.len() - data.len();
+    let length = u32::try_from(length)
+        .ok()
+        .and_then(|length| usize::try_from(length).ok())
+        .filter(|&length| length <= MAX_PACKET_LENGTH)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("packets can't be over {MAX_PACKET_LENGTH} bytes long"),
+            )
+        })?;
+
+    if src.len() < prefix_length + length {
+        src.reserve(prefix_length + length - src.len());
+        return Ok(None);
+    }
+    src.advance(prefix_length);
+    Ok(Some(src.split_to(length)))
+}
+
+/// Decodes a packet from its frame, which it has to use all of
+fn decode_frame<'a, T>(
+    frame: &'a [u8],
+    decode: impl FnOnce(&mut &'a [u8]) -> io::Result<T>,
+) -> io::Result<T> {
+    let mut data = frame;
+    let packet = decode(&mut data)?;
+    if !data.is_empty() {
+        return Err(io::Error::other(format!(
+            "{} bytes were not consumed by the implementation of deserialize_buf",
+            data.len()
+        )));
+    }
+    Ok(packet)
+}
+
+/// Decodes a packet of a client from its frame
+fn decode_packet<P: ServerboundPacket>(frame: &[u8]) -> io::Result<P> {
+    decode_frame(frame, P::deserialize_buf)
+}
+
+/// Adds the packet to the buffer, preceded by its length
+fn encode_frame(packet: &impl ClientboundPacket, dst: &mut BytesMut) -> io::Result<()> {
+    let mut frame = Vec::new();
+    packet.serialize_buf(&mut frame)?;
+    LengthPrefixed::from(frame).serialize_buf(dst)
+}
+
+/// Continues a framed connection with another codec, keeping what was read from it but not decoded yet
+fn into_codec<T, I, C: Encoder<I>>(framed: Framed<T, HandshakeCodec>, codec: C) -> Framed<T, C> {
+    let parts = framed.into_parts();
+    let mut next = FramedParts::new::<I>(parts.io, codec);
+    next.read_buf = parts.read_buf;
+    next.write_buf = parts.write_buf;
+    Framed::from_parts(next)
+}
+
+impl HandshakeCodec {
+    pub fn new(forwarding: Forwarding) -> Self {
+        Self {
+            forwarding,
+            header_read: false,
+            proxied_address: None,
+        }
+    }
+
+    fn decode_server_list_ping(&self, src: &mut BytesMut) -> io::Result<Option<ClientHandshake>> {
+        let Some(header) = src.get(..SERVER_LIST_PING_HEADER_LENGTH) else {
+            return Ok(None);
+        };
+        let length =
+            server_list_ping_length(header.try_into().expect("the slice is the header's length"))?;
+        if src.len() < length {
+            src.reserve(length - src.len());
+            return Ok(None);
+        }
+
+        let frame = src.split_to(length);
+        Ok(Some(ClientHandshake::ServerListPing {
+            packet: decode_packet(&frame)?,
+            proxied_address: self.proxied_address,
+        }))
+    }
+}
+
+impl Decoder for HandshakeCodec {
+    type Item = ClientHandshake;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<ClientHandshake>> {
+        if self.forwarding.proxy_protocol && !self.header_read {
+            let Some((proxied_address, length)) = proxy_protocol::decode_header(src)? else {
+                return Ok(None);
+            };
+            src.advance(length);
+            self.proxied_address = proxied_address;
+            self.header_read = true;
+        }
+
+        // Every handshake is longer than that anyway
+        if src.len() < 3 {
+            return Ok(None);
+        }
+        if starts_server_list_ping(src) {
+            return self.decode_server_list_ping(src);
+        }
+
+        let Some(frame) = split_frame(src)? else {
+            return Ok(None);
+        };
+        let packet: HandshakePacket = decode_packet(&frame)?;
+        let protocol_version = i32::from(packet.protocol_version.clone()).try_into()?;
+
+        let mut bungee_forwarding = None;
+        if self.forwarding.bungeecord {
+            bungee_forwarding = packet.bungee_forwarding();
+            // BungeeCord doesn't forward anything when pinging servers
+            if bungee_forwarding.is_none() && packet.next_state == NextState::Login {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the login handshake has no BungeeCord forwarding data, is ip_forward enabled in BungeeCord?",
+                ));
+            }
+        }
+
+        Ok(Some(ClientHandshake::Handshake {
+            packet,
+            protocol_version,
+            proxied_address: self.proxied_address,
+            bungee_forwarding,
+        }))
+    }
+}
+
+impl StatusCodec {
+    pub fn new(protocol_version: ProtocolVersion) -> Self {
+        Self { protocol_version }
+    }
+
+    /// Continues a framed connection whose handshake asked for the status
+    pub fn after_handshake<T>(
+        framed: Framed<T, HandshakeCodec>,
+        protocol_version: ProtocolVersion,
+    ) -> Framed<T, Self> {
+        into_codec::<_, clientbound::StatusPacket, _>(framed, Self::new(protocol_version))
+    }
+
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
+}
+
+// The status packets are the same in every supported version
+impl Decoder for StatusCodec {
+    type Item = serverbound::StatusPacket;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<serverbound::StatusPacket>> {
+        split_frame(src)?
+            .map(|frame| decode_packet(&frame))
+            .transpose()
+    }
+}
+
+impl Encoder<clientbound::StatusPacket> for StatusCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, packet: clientbound::StatusPacket, dst: &mut BytesMut) -> io::Result<()> {
+        encode_frame(&packet, dst)
+    }
+}
+
+impl LoginCodec {
+    pub fn new(protocol_version: ProtocolVersion) -> Self {
+        Self { protocol_version }
+    }
+
+    /// Continues a framed connection whose handshake asked to log in
+    pub fn after_handshake<T>(
+        framed: Framed<T, HandshakeCodec>,
+        protocol_version: ProtocolVersion,
+    ) -> Framed<T, Self> {
+        into_codec::<_, clientbound::LoginPacket, _>(framed, Self::new(protocol_version))
+    }
+
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
+}
+
+impl Decoder for LoginCodec {
+    type Item = ServerboundLogin;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<ServerboundLogin>> {
+        let protocol_version = self.protocol_version;
+        split_frame(src)?
+            .map(|frame| {
+                decode_frame(&frame, |data| {
+                    ServerboundLogin::deserialize_buf(data, protocol_version)
+                })
+            })
+            .transpose()
+    }
+}
+
+// The login packets we send are the same in every supported version
+impl Encoder<clientbound::LoginPacket> for LoginCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, packet: clientbound::LoginPacket, dst: &mut BytesMut) -> io::Result<()> {
+        encode_frame(&packet, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Handshake for 1.19.2 to localhost:25565, asking for the status
+    const STATUS_HANDSHAKE: &[u8] = b"\x10\x00\xf8\x05\x09localhost\x63\xdd\x01";
+
+    #[test]
+    fn status_codec_test() {
+        let mut handshake = HandshakeCodec::new(Forwarding::default());
+        // Then a status request and a ping
+        let mut src = BytesMut::from(STATUS_HANDSHAKE);
+        src.extend_from_slice(b"\x01\x00\x09\x01\x00\x00\x00\x00\x00\x00\x00\x2a");
+
+        let mut partial = src.split_to(5);
+        assert!(handshake.decode(&mut partial).unwrap().is_none());
+        partial.unsplit(src);
+        let mut src = partial;
+
+        let protocol_version = match handshake.decode(&mut src).unwrap() {
+            Some(ClientHandshake::Handshake {
+                packet,
+                protocol_version,
+                proxied_address: None,
+                bungee_forwarding: None,
+            }) => {
+                assert_eq!("localhost", packet.hostname());
+                assert_eq!(NextState::Status, packet.next_state);
+                protocol_version
+            }
+            other => panic!("expected a handshake, got {other:?}"),
+        };
+
+        let mut status = StatusCodec::new(protocol_version);
+        assert!(matches!(
+            status.decode(&mut src).unwrap(),
+            Some(serverbound::StatusPacket::StatusRequest {})
+        ));
+        assert!(matches!(
+            status.decode(&mut src).unwrap(),
+            Some(serverbound::StatusPacket::PingRequest { payload: 42 })
+        ));
+        assert!(src.is_empty());
+        assert!(status.decode(&mut src).unwrap().is_none());
+
+        let mut dst = BytesMut::new();
+        status
+            .encode(
+                clientbound::StatusPacket::PingResponse { payload: 42 },
+                &mut dst,
+            )
+            .unwrap();
+        assert_eq!(&b"\x09\x01\x00\x00\x00\x00\x00\x00\x00\x2a"[..], &dst[..]);
+
+        // Packets have to use all of their frame
+        let mut src = BytesMut::from(&b"\x02\x00\x00"[..]);
+        assert!(status.decode(&mut src).is_err());
+    }
+
+    #[test]
+    fn forwarding_test() {
+        let client: SocketAddr = "192.0.2.1:56324".parse().unwrap();
+        let mut codec = HandshakeCodec::new(Forwarding {
+            proxy_protocol: true,
+            bungeecord: true,
+        });
+        let header = proxy_protocol::v2_header(client, "127.0.0.1:25565".parse().unwrap());
+
+        let mut src = BytesMut::from(&header[..10]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        src.extend_from_slice(&header[10..]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        // BungeeCord doesn't forward anything when pinging servers
+        src.extend_from_slice(STATUS_HANDSHAKE);
+        assert!(matches!(
+            codec.decode(&mut src).unwrap(),
+            Some(ClientHandshake::Handshake {
+                proxied_address: Some(address),
+                bungee_forwarding: None,
+                ..
+            }) if address == client
+        ));
+
+        let mut login = BytesMut::from(&b"\x10\x00\xf8\x05\x09localhost\x63\xdd\x02"[..]);
+        assert!(HandshakeCodec::new(Forwarding {
+            proxy_protocol: false,
+            bungeecord: true,
+        })
+        .decode(&mut login)
+        .is_err());
+
+        // Without its PROXY header
+        let mut src = BytesMut::from(STATUS_HANDSHAKE);
+        assert!(HandshakeCodec::new(Forwarding {
+            proxy_protocol: true,
+            bungeecord: false,
+        })
+        .decode(&mut src)
+        .is_err());
+    }
+
+    #[test]
+    fn server_list_ping_test() {
+        let mut src = BytesMut::new();
+        ServerListPingPacket {
+            protocol_version: 74,
+            server_address: "localhost".to_owned(),
+            server_port: 25565,
+        }
+        .serialize_buf(&mut src)
+        .unwrap();
+        let mut partial = src.split_to(SERVER_LIST_PING_HEADER_LENGTH + 2);
+
+        let mut codec = HandshakeCodec::new(Forwarding::default());
+        assert!(codec.decode(&mut partial).unwrap().is_none());
+        partial.unsplit(src);
+        match codec.decode(&mut partial).unwrap() {
+            Some(ClientHandshake::ServerListPing { packet, .. }) => {
+                assert_eq!("localhost", packet.server_address);
+                assert_eq!(25565, packet.server_port);
+            }
+            other => panic!("expected a server list ping, got {other:?}"),
+        }
+        assert!(partial.is_empty());
+    }
+}
@@ -3,50 +3,67 @@ pub mod data_types;
 pub mod serverbound_packets;
 
 mod codec;
-pub use codec::ServerCodec;
+pub use codec::{Handshake, ServerCodec};
+pub mod proxy_protocol;
 
+mod framed;
+pub use framed::{ClientHandshake, Forwarding, HandshakeCodec, LoginCodec, StatusCodec};
+
+#[doc(hidden)]
+pub mod derive_support;
+/// Generate each direction of a packet's encoding, see the `mc_protocol_derive` crate for their attributes
+pub use mc_protocol_derive::{Decode, Encode};
+
+use bytes::{Buf, BufMut, TryGetError};
 use std::fmt::{Debug, Display};
 use std::marker::{Send, Unpin};
-use tokio::io;
+use tokio::io::{self, AsyncWriteExt};
 
-/// Something is McProtocol if it can serialize / deserialize itself
-/// according to the minecraft server protocol
+/// Something is Encode if it can serialize itself according to the minecraft server protocol
 #[async_trait::async_trait]
-pub trait McProtocol {
+pub trait Encode {
+    fn serialize_buf<B: BufMut>(&self, buf: &mut B) -> io::Result<()>;
+
+    /// Serializes in memory first, so nothing is written when serializing fails
     async fn serialize_write<W>(&self, writer: &mut W) -> io::Result<()>
     where
-        W: io::AsyncWrite + Unpin + Send;
-    async fn deserialize_read<R>(reader: &mut R) -> io::Result<Self>
-    where
-        Self: std::marker::Sized,
-        R: io::AsyncRead + Unpin + Send;
+        W: io::AsyncWrite + Unpin + Send,
+    {
+        let mut bytes = Vec::new();
+        self.serialize_buf(&mut bytes)?;
+        writer.write_all(&bytes).await
+    }
 }
 
+/// Something is Decode if it can deserialize itself according to the minecraft server protocol
 #[async_trait::async_trait]
-pub trait ProtocolVersionLevelDeserialize {
-    async fn deserialize_read<R>(
-        reader: &mut R,
-        connection_state: ConnectionState,
-        protocol_version: ProtocolVersion,
-    ) -> io::Result<Self>
+pub trait Decode: Sized {
+    async fn deserialize_read<R>(reader: &mut R) -> io::Result<Self>
     where
-        Self: std::marker::Sized,
         R: io::AsyncRead + Unpin + Send;
+
+    /// Deserializes from data already in memory, like a whole packet
+    fn deserialize_buf<B: Buf>(buf: &mut B) -> io::Result<Self>;
 }
 
-#[async_trait::async_trait]
-pub trait ConnectionStateLevelDeserialize {
-    async fn deserialize_read<R>(
-        reader: &mut R,
-        connection_state: ConnectionState,
-    ) -> io::Result<Self>
-    where
-        Self: std::marker::Sized,
-        R: io::AsyncRead + Unpin + Send;
+/// Running out of data in memory is the same error as a stream ending too early
+pub(crate) fn end_of_data(error: TryGetError) -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, error)
 }
 
+/// Something is McProtocol if it can both serialize and deserialize itself
+pub trait McProtocol: Encode + Decode {}
+
+impl<T: Encode + Decode> McProtocol for T {}
+
+/// A packet servers send to clients, which we encode
+pub trait ClientboundPacket: Encode {}
+
+/// A packet clients send to servers, which we decode
+pub trait ServerboundPacket: Decode {}
+
 /// Encodes the currently supported protocol versions
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProtocolVersion {
     V760,
     V761,
@@ -59,10 +76,9 @@ impl TryFrom<i32> for ProtocolVersion {
         match value {
             760 => Ok(Self::V760),
             761 => Ok(Self::V761),
-            other => Err(Self::Error::new(
-                io::ErrorKind::Other,
-                format!("protocol version {other} not supported"),
-            )),
+            other => Err(Self::Error::other(format!(
+                "protocol version {other} not supported"
+            ))),
         }
     }
 }
@@ -72,10 +88,3 @@ impl Display for ProtocolVersion {
         Debug::fmt(&self, f)
     }
 }
-
-#[derive(Debug, Clone, Copy)]
-pub enum ConnectionState {
-    Handshaking,
-    Status,
-    Login,
-}
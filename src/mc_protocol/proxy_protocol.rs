@@ -0,0 +1,253 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::io::{self, AsyncRead, AsyncReadExt};
+
+/// Starts every version 2 header
+const SIGNATURE: [u8; 12] = [
+    0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+/// Longest possible version 1 header, including its line ending
+const V1_MAX_LENGTH: usize = 107;
+/// The signature, version, command, family and length of the addresses of version 2 headers
+const V2_HEADER_LENGTH: usize = 16;
+
+/// Reads the PROXY protocol header (version 1 or 2) sent by load balancers like HAProxy before the connection's data,
+/// and returns the address of the client. Returns None when the proxy doesn't know it, e.g. for its health checks.
+///
+/// Nothing past the header is read, so the stream can still be peeked at afterwards.
+///
+/// # Errors
+/// Returns an error if the connection doesn't start with a valid header
+pub async fn read_header<R>(reader: &mut R) -> io::Result<Option<SocketAddr>>
+where
+    R: AsyncRead + Unpin,
+{
+    match reader.read_u8().await? {
+        b'P' => {
+            // One byte at a time, so we don't read past the line ending
+            let mut line = vec![b'P'];
+            while !line.ends_with(b"\r\n") {
+                if line.len() >= V1_MAX_LENGTH {
+                    return Err(invalid_header("the PROXY header is too long"));
+                }
+                line.push(reader.read_u8().await?);
+            }
+            parse_v1(&line[..line.len() - 2])
+        }
+        first_byte if first_byte == SIGNATURE[0] => {
+            let mut header = [first_byte; V2_HEADER_LENGTH];
+            reader.read_exact(&mut header[1..]).await?;
+            let mut addresses = vec![0; v2_addresses_length(&header)];
+            reader.read_exact(&mut addresses).await?;
+            parse_v2(&header, &addresses)
+        }
+        _ => Err(invalid_header(
+            "the connection doesn't start with a PROXY header",
+        )),
+    }
+}
+
+/// Parses the PROXY header at the start of data in memory, like [`read_header`] does from a stream.
+/// Also returns the length of the header, or None if it isn't all there yet.
+///
+/// # Errors
+/// Returns an error if the data doesn't start with a valid header
+pub fn decode_header(data: &[u8]) -> io::Result<Option<(Option<SocketAddr>, usize)>> {
+    match data.first() {
+        None => Ok(None),
+        Some(b'P') => match data.windows(2).position(|window| window == b"\r\n") {
+            Some(end) if end + 2 <= V1_MAX_LENGTH => Ok(Some((parse_v1(&data[..end])?, end + 2))),
+            None if data.len() < V1_MAX_LENGTH => Ok(None),
+            _ => Err(invalid_header("the PROXY header is too long")),
+        },
+        Some(&first_byte) if first_byte == SIGNATURE[0] => {
+            let Some(header) = data.get(..V2_HEADER_LENGTH) else {
+                return Ok(None);
+            };
+            let header: &[u8; V2_HEADER_LENGTH] =
+                header.try_into().expect("the slice is 16 bytes long");
+            let length = V2_HEADER_LENGTH + v2_addresses_length(header);
+            match data.get(V2_HEADER_LENGTH..length) {
+                Some(addresses) => Ok(Some((parse_v2(header, addresses)?, length))),
+                None => Ok(None),
+            }
+        }
+        Some(_) => Err(invalid_header(
+            "the connection doesn't start with a PROXY header",
+        )),
+    }
+}
+
+/// Parses a text header without its line ending, e.g. `PROXY TCP4 192.0.2.1 198.51.100.1 56324 25565`
+fn parse_v1(line: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line)
+        .map_err(|_| invalid_header("the PROXY header isn't valid text"))?;
+
+    let mut fields = line.split(' ');
+    if fields.next() != Some("PROXY") {
+        return Err(invalid_header(
+            "the PROXY header doesn't start with 'PROXY'",
+        ));
+    }
+    match fields.next() {
+        Some("TCP4" | "TCP6") => {}
+        Some("UNKNOWN") => return Ok(None),
+        _ => return Err(invalid_header("unsupported protocol in the PROXY header")),
+    }
+    let source: IpAddr = fields
+        .next()
+        .and_then(|address| address.parse().ok())
+        .ok_or_else(|| invalid_header("invalid source address in the PROXY header"))?;
+    let port: u16 = fields
+        .nth(1)
+        .and_then(|port| port.parse().ok())
+        .ok_or_else(|| invalid_header("invalid source port in the PROXY header"))?;
+
+    Ok(Some(SocketAddr::new(source, port)))
+}
+
+/// The length of the addresses following the fixed part of a binary header
+fn v2_addresses_length(header: &[u8; V2_HEADER_LENGTH]) -> usize {
+    usize::from(u16::from_be_bytes([header[14], header[15]]))
+}
+
+/// Parses a binary header from its fixed part and the addresses following it
+fn parse_v2(header: &[u8; V2_HEADER_LENGTH], addresses: &[u8]) -> io::Result<Option<SocketAddr>> {
+    if header[..12] != SIGNATURE {
+        return Err(invalid_header("invalid PROXY header signature"));
+    }
+
+    let version_command = header[12];
+    let family = header[13];
+
+    if version_command >> 4 != 2 {
+        return Err(invalid_header("unsupported PROXY header version"));
+    }
+    // LOCAL connections are made by the proxy itself
+    if version_command & 0x0F == 0 {
+        return Ok(None);
+    }
+
+    // Anything after the addresses (TLVs) is ignored
+    Ok(match family {
+        // TCP over IPv4
+        0x11 if addresses.len() >= 12 => {
+            let ip: [u8; 4] = addresses[..4]
+                .try_into()
+                .expect("the slice is 4 bytes long");
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Some(SocketAddr::new(Ipv4Addr::from(ip).into(), port))
+        }
+        // TCP over IPv6
+        0x21 if addresses.len() >= 36 => {
+            let ip: [u8; 16] = addresses[..16]
+                .try_into()
+                .expect("the slice is 16 bytes long");
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port))
+        }
+        // Unix sockets, UDP or unspecified
+        _ => None,
+    })
+}
+
+/// Builds the binary header telling the server behind us that a connection from `source` was made to `destination`.
+/// IPv4 addresses are mapped to IPv6 when the other one is IPv6, as both have to be of the same family.
+pub fn v2_header(source: SocketAddr, destination: SocketAddr) -> Vec<u8> {
+    let mut header = SIGNATURE.to_vec();
+    // Version 2, PROXY command
+    header.push(0x21);
+    match (source.ip(), destination.ip()) {
+        (IpAddr::V4(source_ip), IpAddr::V4(destination_ip)) => {
+            header.push(0x11);
+            header.extend_from_slice(&12u16.to_be_bytes());
+            header.extend_from_slice(&source_ip.octets());
+            header.extend_from_slice(&destination_ip.octets());
+        }
+        (source_ip, destination_ip) => {
+            let v6 = |ip: IpAddr| match ip {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            };
+            header.push(0x21);
+            header.extend_from_slice(&36u16.to_be_bytes());
+            header.extend_from_slice(&v6(source_ip).octets());
+            header.extend_from_slice(&v6(destination_ip).octets());
+        }
+    }
+    header.extend_from_slice(&source.port().to_be_bytes());
+    header.extend_from_slice(&destination.port().to_be_bytes());
+    header
+}
+
+fn invalid_header(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn read_header_test() {
+        let mut v1: &[u8] = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 25565\r\n\x10";
+        assert_eq!(
+            Some("192.0.2.1:56324".parse().unwrap()),
+            read_header(&mut v1).await.unwrap()
+        );
+        // The data after the header is left untouched
+        assert_eq!(b"\x10", v1);
+
+        let mut unknown: &[u8] = b"PROXY UNKNOWN\r\n";
+        assert_eq!(None, read_header(&mut unknown).await.unwrap());
+
+        let mut v2 = SIGNATURE.to_vec();
+        v2.extend_from_slice(&[0x21, 0x21, 0, 36]);
+        v2.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        v2.extend_from_slice(&[0; 16]);
+        v2.extend_from_slice(&[0xDC, 0x04, 0x63, 0xDD, 0x10]);
+        let mut v2 = v2.as_slice();
+        assert_eq!(
+            Some("[2001:db8::1]:56324".parse().unwrap()),
+            read_header(&mut v2).await.unwrap()
+        );
+        assert_eq!(b"\x10", v2);
+
+        let mut minecraft: &[u8] = b"\x10\x00\xf8\x05";
+        assert!(read_header(&mut minecraft).await.is_err());
+    }
+
+    #[test]
+    fn decode_header_test() {
+        let v1 = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 25565\r\n\x10";
+        assert_eq!(None, decode_header(&v1[..20]).unwrap());
+        assert_eq!(
+            Some((Some("192.0.2.1:56324".parse().unwrap()), v1.len() - 1)),
+            decode_header(v1).unwrap()
+        );
+        assert!(decode_header(&[b'P'; V1_MAX_LENGTH]).is_err());
+
+        let client: SocketAddr = "192.0.2.1:56324".parse().unwrap();
+        let mut v2 = v2_header(client, "127.0.0.1:25565".parse().unwrap());
+        let length = v2.len();
+        assert_eq!(None, decode_header(&v2[..V2_HEADER_LENGTH]).unwrap());
+        v2.push(0x10);
+        assert_eq!(Some((Some(client), length)), decode_header(&v2).unwrap());
+
+        assert_eq!(None, decode_header(b"").unwrap());
+        assert!(decode_header(b"\x10\x00\xf8\x05").is_err());
+    }
+
+    #[tokio::test]
+    async fn v2_header_test() {
+        let server: SocketAddr = "127.0.0.1:25565".parse().unwrap();
+        for client in ["192.0.2.1:56324", "[2001:db8::1]:56324"] {
+            let client: SocketAddr = client.parse().unwrap();
+            let header = v2_header(client, server);
+            assert_eq!(
+                Some(client),
+                read_header(&mut header.as_slice()).await.unwrap()
+            );
+        }
+    }
+}
@@ -1,17 +1,98 @@
-use crate::mc_protocol::data_types::McVarint;
-use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+use crate::mc_protocol::{data_types::McVarint, Decode, Encode, ServerboundPacket};
+use std::net::IpAddr;
 
-#[derive(Debug)]
+/// Encoded too, as the status probe starts its connections to the minecraft server like a client
+#[derive(Debug, Encode, Decode)]
+#[mc(id = 0)]
 pub struct HandshakePacket {
     pub protocol_version: McVarint,
+    /// Not limited to 255 bytes like a hostname, as proxies and mod loaders append their data to it
     pub server_address: String,
     pub server_port: u16,
     pub next_state: NextState,
 }
 
-#[derive(Debug)]
+/// What BungeeCord appends to the server address when its `ip_forward` option is enabled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BungeeForwarding {
+    pub address: IpAddr,
+    pub uuid: u128,
+    /// The skin and other properties of the player, as JSON
+    pub properties: Option<String>,
+}
+
+impl ServerboundPacket for HandshakePacket {}
+
+impl HandshakePacket {
+    /// The address the client connected to, without the data proxies and mod loaders append to it
+    pub fn hostname(&self) -> &str {
+        self.server_address.split('\0').next().unwrap_or_default()
+    }
+
+    /// The marker Forge clients append to the server address, if there is one
+    pub fn fml_marker(&self) -> Option<FmlMarker> {
+        self.server_address
+            .split('\0')
+            .skip(1)
+            .find_map(|field| match field {
+                "FML" => Some(FmlMarker::Fml),
+                "FML2" => Some(FmlMarker::Fml2),
+                "FML3" => Some(FmlMarker::Fml3),
+                _ => None,
+            })
+    }
+
+    /// The client information forwarded by BungeeCord, if there is any
+    pub fn bungee_forwarding(&self) -> Option<BungeeForwarding> {
+        // Mod loaders markers may come before the forwarded data: `hostname\0FML\0\0ip\0uuid\0properties`
+        let fields: Vec<&str> = self.server_address.split('\0').skip(1).collect();
+        fields.windows(2).enumerate().find_map(|(index, window)| {
+            let address: IpAddr = window[0].parse().ok()?;
+            let uuid = window[1].replace('-', "");
+            if uuid.len() != 32 {
+                return None;
+            }
+            Some(BungeeForwarding {
+                address: address.to_canonical(),
+                uuid: u128::from_str_radix(&uuid, 16).ok()?,
+                properties: fields
+                    .get(index + 2)
+                    .map(|&properties| properties.to_owned()),
+            })
+        })
+    }
+}
+
+/// Version of the Forge Mod Loader handshake a modded client speaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FmlMarker {
+    /// Minecraft 1.7 to 1.12
+    Fml,
+    /// Minecraft 1.13 to 1.17
+    Fml2,
+    /// Minecraft 1.18 and later
+    Fml3,
+}
+
+impl std::fmt::Display for FmlMarker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Fml => "FML",
+                Self::Fml2 => "FML2",
+                Self::Fml3 => "FML3",
+            }
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Encode, Decode)]
 pub enum NextState {
+    #[mc(id = 2)]
     Login,
+    #[mc(id = 1)]
     Status,
 }
 
@@ -28,64 +109,69 @@ impl std::fmt::Display for NextState {
     }
 }
 
-#[async_trait::async_trait]
-impl crate::mc_protocol::McProtocol for HandshakePacket {
-    async fn deserialize_read<R>(reader: &mut R) -> io::Result<Self>
-    where
-        R: io::AsyncRead + Unpin + Send,
-    {
-        {
-            let packet_id = reader.read_u8().await?;
-            if packet_id != 0 {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("unexpected packet ID: {packet_id}"),
-                ));
-            }
-        }
-        let protocol_version = McVarint::deserialize_read(reader).await?;
-        let server_address = String::deserialize_read(reader).await?;
-        let server_port = reader.read_u16().await?;
-        let next_state = match reader.read_u8().await? {
-            1 => NextState::Status,
-            2 => NextState::Login,
-            _ => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "next_state field wasn't a valid enum variant",
-                ))
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bungee_forwarding_test() {
+        let handshake = |server_address: &str| HandshakePacket {
+            protocol_version: McVarint::from(760_i32),
+            server_address: server_address.to_owned(),
+            server_port: 25565,
+            next_state: NextState::Login,
         };
 
-        Ok(HandshakePacket {
-            protocol_version,
-            server_address,
-            server_port,
-            next_state,
-        })
-    }
+        let forwarded = handshake(
+            "play.example.com\x00203.0.113.7\x00069a79f444e94726a5befca90e38aaf5\0[{\"name\":\"textures\"}]",
+        );
+        assert_eq!("play.example.com", forwarded.hostname());
+        assert_eq!(
+            Some(BungeeForwarding {
+                address: "203.0.113.7".parse().unwrap(),
+                uuid: 0x069a79f444e94726a5befca90e38aaf5,
+                properties: Some("[{\"name\":\"textures\"}]".to_owned()),
+            }),
+            forwarded.bungee_forwarding()
+        );
 
-    async fn serialize_write<W>(&self, writer: &mut W) -> io::Result<()>
-    where
-        W: io::AsyncWrite + Unpin + Send,
-    {
-        writer.write_u8(0).await?;
-        self.protocol_version.serialize_write(writer).await?;
-        if self.server_address.as_bytes().len() > 255 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "server_address can't be over 255 bytes long",
-            ));
-        }
-        self.server_address.serialize_write(writer).await?;
-        writer.write_u16(self.server_port).await?;
-        writer
-            .write_u8(match self.next_state {
-                NextState::Status => 1,
-                NextState::Login => 2,
-            })
-            .await?;
+        let modded = handshake(
+            "play.example.com\0FML\0\x00203.0.113.7\x00069a79f4-44e9-4726-a5be-fca90e38aaf5",
+        );
+        assert_eq!(
+            Some("203.0.113.7".parse().unwrap()),
+            modded
+                .bungee_forwarding()
+                .map(|forwarding| forwarding.address)
+        );
+        assert_eq!("play.example.com", modded.hostname());
+        assert_eq!(Some(FmlMarker::Fml), modded.fml_marker());
+        assert_eq!(None, forwarded.fml_marker());
+        assert_eq!(
+            Some(FmlMarker::Fml3),
+            handshake("play.example.com\0FML3\0").fml_marker()
+        );
 
-        Ok(())
+        assert_eq!(None, handshake("play.example.com").bungee_forwarding());
+    }
+
+    #[test]
+    fn fml_marker_test() {
+        let fml_marker = |server_address: &str| {
+            HandshakePacket {
+                protocol_version: McVarint::from(760_i32),
+                server_address: server_address.to_owned(),
+                server_port: 25565,
+                next_state: NextState::Status,
+            }
+            .fml_marker()
+        };
+        assert_eq!(Some(FmlMarker::Fml), fml_marker("localhost\0FML\0"));
+        assert_eq!(Some(FmlMarker::Fml2), fml_marker("localhost\0FML2\0"));
+        assert_eq!(Some(FmlMarker::Fml3), fml_marker("localhost\0FML3\0"));
+        assert_eq!(None, fml_marker("localhost"));
+        // Only markers after the hostname count
+        assert_eq!(None, fml_marker("FML3"));
+        assert_eq!(None, fml_marker("localhost\0FML4\0"));
     }
 }
@@ -1,11 +1,9 @@
 mod handshake;
-pub use handshake::{HandshakePacket, NextState};
+pub use handshake::{BungeeForwarding, HandshakePacket, NextState};
 
 mod server_list_ping;
 pub use server_list_ping::{is_packet_server_list_ping, ServerListPingPacket};
-
-#[derive(Debug)]
-pub enum Generic {
-    Handshake(HandshakePacket),
-    ServerListPing(ServerListPingPacket),
-}
+pub(crate) use server_list_ping::{
+    packet_length as server_list_ping_length, starts_server_list_ping,
+    HEADER_LENGTH as SERVER_LIST_PING_HEADER_LENGTH,
+};
@@ -1,6 +1,7 @@
-use crate::mc_protocol::McProtocol;
+use crate::mc_protocol::{end_of_data, Decode, Encode, ServerboundPacket};
+use bytes::{Buf, BufMut};
 use tokio::{
-    io::{self, AsyncReadExt, AsyncWriteExt},
+    io::{self, AsyncReadExt},
     net::tcp::OwnedReadHalf,
 };
 
@@ -9,21 +10,24 @@ const STATIC_HEADER: [u8; 27] = [
     0xfe, 0x01, 0xfa, 0x00, 0x0b, 0x00, 0x4D, 0x00, 0x43, 0x00, 0x7C, 0x00, 0x50, 0x00, 0x69, 0x00,
     0x6E, 0x00, 0x67, 0x00, 0x48, 0x00, 0x6F, 0x00, 0x73, 0x00, 0x74,
 ];
+/// Bytes of a server list ping up to the length of the rest of its data
+pub(crate) const HEADER_LENGTH: usize = 29;
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub struct ServerListPingPacket {
-    protocol_version: u8,
-    server_address: String,
-    server_port: i32,
+    pub protocol_version: u8,
+    pub server_address: String,
+    pub server_port: i32,
 }
 
+impl ServerboundPacket for ServerListPingPacket {}
+
 /// Will call peek on the read half and attempt to read 3 bytes from the stream.
 /// If they match the first bytes sent by a server list ping, returns true.
 ///
 /// # Errors
 /// Returns an error if it can't read from the reader or if the number of bytes read differs from 3
-pub async fn is_packet_server_list_ping<R>(reader: &mut OwnedReadHalf) -> io::Result<bool> {
+pub async fn is_packet_server_list_ping(reader: &mut OwnedReadHalf) -> io::Result<bool> {
     let mut peeked_bytes = [0u8; 3];
     if reader.peek(&mut peeked_bytes).await? != 3 {
         return Err(io::Error::new(
@@ -31,62 +35,84 @@ pub async fn is_packet_server_list_ping<R>(reader: &mut OwnedReadHalf) -> io::Re
             "expected at least 3 bytes",
         ));
     };
-    Ok(peeked_bytes == [0xfe, 0x01, 0xfa])
+    Ok(starts_server_list_ping(&peeked_bytes))
 }
 
-#[async_trait::async_trait]
-impl McProtocol for ServerListPingPacket {
-    async fn serialize_write<W>(&self, writer: &mut W) -> io::Result<()>
-    where
-        W: io::AsyncWrite + Unpin + Send,
-    {
-        writer.write_all(&STATIC_HEADER).await?;
+/// Whether the bytes start like a server list ping, which only takes its first 3 bytes
+pub(crate) fn starts_server_list_ping(bytes: &[u8]) -> bool {
+    bytes.starts_with(&STATIC_HEADER[..3])
+}
 
-        writer
-            .write_i16(match self.server_address.len().try_into() {
-                Ok(length) => length,
-                Err(_) => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        "server address length was outside i16 bounds",
-                    ))
-                }
-            })
-            .await?;
+/// The length of the whole server list ping starting with `header`
+pub(crate) fn packet_length(header: &[u8; HEADER_LENGTH]) -> io::Result<usize> {
+    // The protocol version, the length of the hostname in characters, and the port come with it
+    match usize::try_from(i16::from_be_bytes([header[27], header[28]])) {
+        Ok(remaining) if remaining >= 7 => Ok(HEADER_LENGTH + remaining),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid server list ping length",
+        )),
+    }
+}
 
-        writer.write_u8(self.protocol_version).await?;
+impl Encode for ServerListPingPacket {
+    fn serialize_buf<B: BufMut>(&self, buf: &mut B) -> io::Result<()> {
+        buf.put_slice(&STATIC_HEADER);
 
-        writer
-            .write_i16(match self.server_address.chars().count().try_into() {
-                Ok(length) => length,
-                Err(_) => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        "server address length in characters was outside i16 bounds",
-                    ))
-                }
-            })
-            .await?;
+        // The protocol version, the length of the hostname in characters, the hostname and the port
+        let data_length = 7 + 2 * self.server_address.encode_utf16().count();
+        buf.put_i16(match data_length.try_into() {
+            Ok(length) => length,
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "server address length was outside i16 bounds",
+                ))
+            }
+        });
+
+        buf.put_u8(self.protocol_version);
 
-        let mut server_address_bytes = Vec::<u8>::new();
-        self.server_address.encode_utf16().for_each(|byte_pair| {
-            server_address_bytes.push((byte_pair >> 8) as u8);
-            server_address_bytes.push(byte_pair as u8);
+        buf.put_i16(match self.server_address.chars().count().try_into() {
+            Ok(length) => length,
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "server address length in characters was outside i16 bounds",
+                ))
+            }
         });
-        writer.write_all(server_address_bytes.as_slice()).await?;
 
-        writer.write_i32(self.server_port).await?;
+        self.server_address
+            .encode_utf16()
+            .for_each(|byte_pair| buf.put_u16(byte_pair));
+
+        buf.put_i32(self.server_port);
 
         Ok(())
     }
+}
+
+#[async_trait::async_trait]
+impl Decode for ServerListPingPacket {
+    /// Reads exactly the bytes of the packet, as its length is known from its header
     async fn deserialize_read<R>(reader: &mut R) -> io::Result<Self>
     where
-        Self: std::marker::Sized,
         R: io::AsyncRead + Unpin + Send,
     {
+        let mut header = [0u8; HEADER_LENGTH];
+        reader.read_exact(&mut header).await?;
+        let mut packet = vec![0u8; packet_length(&header)?];
+        packet[..HEADER_LENGTH].copy_from_slice(&header);
+        reader.read_exact(&mut packet[HEADER_LENGTH..]).await?;
+
+        Self::deserialize_buf(&mut packet.as_slice())
+    }
+
+    fn deserialize_buf<B: Buf>(buf: &mut B) -> io::Result<Self> {
         {
             let mut header = [0u8; 27];
-            reader.read_exact(&mut header).await?;
+            buf.try_copy_to_slice(&mut header).map_err(end_of_data)?;
             if header != STATIC_HEADER {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
@@ -95,9 +121,9 @@ impl McProtocol for ServerListPingPacket {
             }
         }
 
-        let hostname_length_bytes = match usize::try_from(reader.read_i16().await?) {
-            Ok(value) => value,
-            Err(_) => {
+        let hostname_length_bytes = match usize::try_from(buf.try_get_i16().map_err(end_of_data)?) {
+            Ok(value) if value >= 7 => value,
+            _ => {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
                     "packet data length was outside of usize bounds (probably negative)",
@@ -105,10 +131,10 @@ impl McProtocol for ServerListPingPacket {
             }
         } - 7;
 
-        let protocol_version = reader.read_u8().await?;
+        let protocol_version = buf.try_get_u8().map_err(end_of_data)?;
 
         let server_address = {
-            let hostname_length_chars = match usize::try_from(reader.read_i16().await?) {
+            let hostname_length_chars = match usize::try_from(buf.try_get_i16().map_err(end_of_data)?) {
                 Ok(value) => value,
                 Err(_) => return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
@@ -118,7 +144,7 @@ impl McProtocol for ServerListPingPacket {
 
             let mut buffer = Vec::<u16>::with_capacity(hostname_length_bytes / 2);
             for _ in 0..(hostname_length_bytes / 2) {
-                buffer.push(reader.read_u16().await?);
+                buffer.push(buf.try_get_u16().map_err(end_of_data)?);
             }
 
             let server_address = match String::from_utf16(buffer.as_slice()) {
@@ -141,7 +167,7 @@ impl McProtocol for ServerListPingPacket {
             server_address
         };
 
-        let server_port = reader.read_i32().await?;
+        let server_port = buf.try_get_i32().map_err(end_of_data)?;
 
         Ok(ServerListPingPacket {
             protocol_version,
@@ -150,3 +176,38 @@ impl McProtocol for ServerListPingPacket {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn server_list_ping_test() {
+        let packet = ServerListPingPacket {
+            protocol_version: 74,
+            server_address: "localhost".to_owned(),
+            server_port: 25565,
+        };
+        let mut bytes = Vec::new();
+        packet.serialize_buf(&mut bytes).unwrap();
+        assert_eq!(
+            bytes.len(),
+            packet_length(bytes[..HEADER_LENGTH].try_into().unwrap()).unwrap()
+        );
+
+        // Nothing after the packet is read
+        bytes.push(0x2a);
+        let mut reader = bytes.as_slice();
+        let read = ServerListPingPacket::deserialize_read(&mut reader)
+            .await
+            .unwrap();
+        assert_eq!(b"\x2a", reader);
+        assert_eq!("localhost", read.server_address);
+        assert_eq!(74, read.protocol_version);
+        assert_eq!(25565, read.server_port);
+
+        let mut header = [0u8; HEADER_LENGTH];
+        header[..27].copy_from_slice(&STATIC_HEADER);
+        assert!(packet_length(&header).is_err());
+    }
+}
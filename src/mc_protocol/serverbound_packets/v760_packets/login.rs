@@ -1,24 +1,32 @@
-use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
-
-use crate::mc_protocol::data_types::{LengthPrefixed, McVarint};
+use crate::mc_protocol::{Decode, ServerboundPacket};
 
 // https://wiki.vg/index.php?title=Protocol&oldid=17873#Login_Start
 
-#[derive(Debug)]
+#[derive(Debug, Decode)]
+#[mc(unsupported(encryption_response = 1, login_plugin_response = 2))]
 pub enum LoginPacket {
+    #[mc(id = 0)]
     LoginStart {
+        #[mc(max_length = 16)]
         name: String,
+        #[mc(optional)]
         sig_data: Option<SigData>,
+        #[mc(optional)]
         player_uuid: Option<u128>,
     },
 }
 
+#[derive(Decode)]
 pub struct SigData {
     timestamp: i64,
+    #[mc(length_prefixed)]
     public_key: Vec<u8>,
+    #[mc(length_prefixed)]
     signature: Vec<u8>,
 }
 
+impl ServerboundPacket for LoginPacket {}
+
 impl std::fmt::Debug for SigData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("SigData")
@@ -29,134 +37,44 @@ impl std::fmt::Debug for SigData {
     }
 }
 
-#[async_trait::async_trait]
-impl crate::mc_protocol::McProtocol for LoginPacket {
-    async fn serialize_write<W>(&self, writer: &mut W) -> io::Result<()>
-    where
-        W: io::AsyncWrite + Unpin + Send,
-    {
-        match self {
-            Self::LoginStart {
-                name,
-                sig_data,
-                player_uuid,
-            } => {
-                if name.len() > 16 {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "Player name can't be over 16 characters long",
-                    ));
-                };
-                name.serialize_write(writer).await?;
-
-                if let Some(sig_data) = sig_data {
-                    writer.write_u8(1).await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                    writer.write_i64(sig_data.timestamp).await?;
+    #[tokio::test]
+    async fn login_start_test() {
+        let mut bytes = b"\x00\x05Notch\x01".to_vec();
+        bytes.extend(1_700_000_000_i64.to_be_bytes());
+        bytes.extend(b"\x03\x01\x02\x03\x02\x04\x05\x01");
+        bytes.extend(0x069a79f444e94726a5befca90e38aaf5_u128.to_be_bytes());
 
-                    McVarint::from(match i32::try_from(sig_data.public_key.len()) {
-                        Ok(value) => value,
-                        Err(_) => {
-                            return Err(io::Error::new(
-                                io::ErrorKind::InvalidInput,
-                                "public key length was outside of i32 bounds",
-                            ))
-                        }
-                    })
-                    .serialize_write(writer)
-                    .await?;
-                    writer.write_all(&sig_data.public_key).await?;
-
-                    McVarint::from(match i32::try_from(sig_data.signature.len()) {
-                        Ok(value) => value,
-                        Err(_) => {
-                            return Err(io::Error::new(
-                                io::ErrorKind::InvalidInput,
-                                "signature length was outside of i32 bounds",
-                            ))
-                        }
-                    })
-                    .serialize_write(writer)
-                    .await?;
-                    writer.write_all(&sig_data.signature).await?;
-                } else {
-                    writer.write_u8(0).await?;
-                }
-
-                if let Some(uuid) = player_uuid {
-                    writer.write_u8(1).await?;
-                    writer.write_u128(*uuid).await?
-                } else {
-                    writer.write_u8(0).await?;
-                }
-            }
-        }
-        Ok(())
-    }
+        let read = LoginPacket::deserialize_read(&mut bytes.as_slice())
+            .await
+            .unwrap();
+        let LoginPacket::LoginStart {
+            name,
+            sig_data: Some(sig_data),
+            player_uuid,
+        } = read
+        else {
+            panic!("expected a login start with signature data, got {read:?}");
+        };
+        assert_eq!("Notch", name);
+        assert_eq!(1_700_000_000, sig_data.timestamp);
+        assert_eq!(vec![1, 2, 3], sig_data.public_key);
+        assert_eq!(vec![4, 5], sig_data.signature);
+        assert_eq!(Some(0x069a79f444e94726a5befca90e38aaf5), player_uuid);
+        // The same from memory
+        assert!(LoginPacket::deserialize_buf(&mut bytes.as_slice()).is_ok());
 
-    async fn deserialize_read<R>(reader: &mut R) -> io::Result<Self>
-    where
-        Self: std::marker::Sized,
-        R: io::AsyncRead + Unpin + Send,
-    {
-        match reader.read_u8().await? {
-            0 => {
-                let name = String::deserialize_read(reader).await?;
-                if name.len() > 16 {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "Player name can't be over 16 characters long",
-                    ));
-                }
-                let sig_data = match reader.read_u8().await? {
-                    0 => None,
-                    1 => {
-                        let timestamp = reader.read_i64().await?;
-                        let public_key: Vec<u8> =
-                            LengthPrefixed::deserialize_read(reader).await?.into();
-                        let signature: Vec<u8> =
-                            LengthPrefixed::deserialize_read(reader).await?.into();
-                        Some(SigData {
-                            timestamp,
-                            public_key,
-                            signature,
-                        })
-                    }
-                    other => {
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidData,
-                            format!("unexpected boolean variant: {other}"),
-                        ))
-                    }
-                };
-                let player_uuid = match reader.read_u8().await? {
-                    0 => None,
-                    1 => Some(reader.read_u128().await?),
-                    other => {
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidData,
-                            format!("unexpected boolean variant: {other}"),
-                        ))
-                    }
-                };
-                Ok(LoginPacket::LoginStart {
-                    name,
-                    sig_data,
-                    player_uuid,
-                })
-            }
-            1 => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Deserializing encryption response packets is not supported",
-            )),
-            2 => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Deserializing login plugin response packet is not supported",
-            )),
-            other => Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("unexpected packet ID: {other}"),
-            )),
-        }
+        let too_long = b"\x00\x11SeventeenLetters_\x00\x00";
+        assert!(LoginPacket::deserialize_read(&mut &too_long[..])
+            .await
+            .is_err());
+        assert!(LoginPacket::deserialize_read(&mut &b"\x02"[..])
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("not supported"));
     }
 }
@@ -0,0 +1,385 @@
+use std::{
+    hash::{BuildHasher, RandomState},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use clap::{Args, ValueEnum};
+use tokio::{
+    io,
+    net::UdpSocket,
+    task::{self, JoinHandle, JoinSet},
+};
+
+/// Every query packet sent by clients starts with it
+const MAGIC: [u8; 2] = [0xFE, 0xFD];
+const HANDSHAKE: u8 = 9;
+const STAT: u8 = 0;
+/// Constant padding around the sections of a full stat response
+const KEY_VALUE_PADDING: &[u8] = b"splitnum\0\x80\0";
+const PLAYERS_PADDING: &[u8] = b"\x01player_\0\0";
+/// How long a challenge token stays valid. Tokens are accepted for up to twice as long
+const CHALLENGE_LIFETIME: Duration = Duration::from_secs(30);
+/// How long the minecraft server may take to answer a query
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The UDP query protocol (`enable-query` in server.properties), used by server lists and monitoring
+#[derive(Args, Debug)]
+pub struct QueryArgs {
+    /// answer the query protocol on this UDP port while the minecraft server sleeps (its query.port)
+    #[arg(long, value_name = "PORT")]
+    query_port: Option<u16>,
+
+    /// how the player count of the running minecraft server is read.
+    /// The query protocol also gives the names of online players, but requires enable-query
+    #[arg(long, value_enum, default_value_t = PlayerCountSource::Status)]
+    player_count_source: PlayerCountSource,
+
+    /// where to reach the query port of your minecraft server [default: the probe address, on the query port]
+    #[arg(long, value_name = "ADDRESS:PORT")]
+    query_address: Option<SocketAddr>,
+}
+
+impl QueryArgs {
+    pub fn port(&self) -> Option<u16> {
+        self.query_port
+    }
+
+    pub fn player_count_source(&self) -> PlayerCountSource {
+        self.player_count_source
+    }
+
+    /// Where the minecraft server answers queries, given where it answers status requests
+    pub fn address(&self, probe_address: SocketAddr) -> SocketAddr {
+        self.query_address.unwrap_or_else(|| {
+            SocketAddr::new(
+                probe_address.ip(),
+                self.query_port.unwrap_or(probe_address.port()),
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PlayerCountSource {
+    /// The server list status, like the minecraft client
+    Status,
+    /// The full stat of the query protocol
+    Query,
+}
+
+/// What a server tells about itself through the query protocol
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stat {
+    pub motd: String,
+    pub game_type: String,
+    pub version: String,
+    pub plugins: String,
+    pub map: String,
+    pub num_players: u64,
+    pub max_players: u64,
+    pub host_port: u16,
+    pub host_ip: String,
+    pub players: Vec<String>,
+}
+
+impl Stat {
+    fn write_basic(&self, buffer: &mut Vec<u8>) {
+        for value in [
+            &self.motd,
+            &self.game_type,
+            &self.map,
+            &self.num_players.to_string(),
+            &self.max_players.to_string(),
+        ] {
+            write_string(buffer, value);
+        }
+        buffer.extend_from_slice(&self.host_port.to_le_bytes());
+        write_string(buffer, &self.host_ip);
+    }
+
+    fn write_full(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(KEY_VALUE_PADDING);
+        for (key, value) in [
+            ("hostname", self.motd.clone()),
+            ("gametype", self.game_type.clone()),
+            ("game_id", "MINECRAFT".to_owned()),
+            ("version", self.version.clone()),
+            ("plugins", self.plugins.clone()),
+            ("map", self.map.clone()),
+            ("numplayers", self.num_players.to_string()),
+            ("maxplayers", self.max_players.to_string()),
+            ("hostport", self.host_port.to_string()),
+            ("hostip", self.host_ip.clone()),
+        ] {
+            write_string(buffer, key);
+            write_string(buffer, &value);
+        }
+        buffer.push(0);
+
+        buffer.extend_from_slice(PLAYERS_PADDING);
+        for player in &self.players {
+            write_string(buffer, player);
+        }
+        buffer.push(0);
+    }
+
+    fn read_full(mut bytes: &[u8]) -> io::Result<Self> {
+        bytes = bytes
+            .strip_prefix(KEY_VALUE_PADDING)
+            .ok_or_else(|| invalid_data("missing padding before the key-value section"))?;
+
+        let mut stat = Stat::default();
+        loop {
+            let key = read_string(&mut bytes)?;
+            if key.is_empty() {
+                break;
+            }
+            let value = read_string(&mut bytes)?;
+            let number = |value: &str| {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| invalid_data(&format!("{key} is not a number: '{value}'")))
+            };
+            match key.as_str() {
+                "hostname" => stat.motd = value,
+                "gametype" => stat.game_type = value,
+                "version" => stat.version = value,
+                "plugins" => stat.plugins = value,
+                "map" => stat.map = value,
+                "numplayers" => stat.num_players = number(&value)?,
+                "maxplayers" => stat.max_players = number(&value)?,
+                "hostport" => stat.host_port = number(&value)? as u16,
+                "hostip" => stat.host_ip = value,
+                _ => {}
+            }
+        }
+
+        bytes = bytes
+            .strip_prefix(PLAYERS_PADDING)
+            .ok_or_else(|| invalid_data("missing padding before the player section"))?;
+        loop {
+            let player = read_string(&mut bytes)?;
+            if player.is_empty() {
+                break;
+            }
+            stat.players.push(player);
+        }
+
+        Ok(stat)
+    }
+}
+
+fn write_string(buffer: &mut Vec<u8>, value: &str) {
+    buffer.extend_from_slice(value.as_bytes());
+    buffer.push(0);
+}
+
+/// Reads a null-terminated string and advances `bytes` past it
+fn read_string(bytes: &mut &[u8]) -> io::Result<String> {
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| invalid_data("unterminated string"))?;
+    let string = String::from_utf8_lossy(&bytes[..end]).into_owned();
+    *bytes = &bytes[end + 1..];
+    Ok(string)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+/// Challenge tokens derived from the address of the client, so none need to be stored
+struct Challenges {
+    secret: RandomState,
+    start: Instant,
+}
+
+impl Challenges {
+    fn new() -> Self {
+        Self {
+            secret: RandomState::new(),
+            start: Instant::now(),
+        }
+    }
+
+    fn window(&self) -> u64 {
+        (self.start.elapsed().as_secs()) / CHALLENGE_LIFETIME.as_secs()
+    }
+
+    fn token(&self, ip: IpAddr, window: u64) -> i32 {
+        self.secret.hash_one((ip, window)) as i32
+    }
+
+    fn issue(&self, ip: IpAddr) -> i32 {
+        self.token(ip, self.window())
+    }
+
+    fn check(&self, ip: IpAddr, token: i32) -> bool {
+        let window = self.window();
+        token == self.token(ip, window) || (window > 0 && token == self.token(ip, window - 1))
+    }
+}
+
+/// Builds the answer to a query packet, or returns None for packets that don't deserve one
+fn respond(packet: &[u8], ip: IpAddr, challenges: &Challenges, stat: &Stat) -> Option<Vec<u8>> {
+    let packet = packet.strip_prefix(&MAGIC)?;
+    let (&kind, packet) = packet.split_first()?;
+    let session: [u8; 4] = packet.get(..4)?.try_into().ok()?;
+    let payload = &packet[4..];
+
+    let mut response = vec![kind];
+    response.extend_from_slice(&session);
+    match kind {
+        HANDSHAKE => write_string(&mut response, &challenges.issue(ip).to_string()),
+        STAT => {
+            let token = i32::from_be_bytes(payload.get(..4)?.try_into().ok()?);
+            if !challenges.check(ip, token) {
+                return None;
+            }
+            // Full stat requests are padded to 8 bytes
+            if payload.len() >= 8 {
+                stat.write_full(&mut response);
+            } else {
+                stat.write_basic(&mut response);
+            }
+        }
+        _ => return None,
+    }
+    Some(response)
+}
+
+/// Answers queries on every socket with `stat` until the returned task is aborted
+pub fn spawn_responder(sockets: Vec<UdpSocket>, stat: Stat) -> JoinHandle<()> {
+    task::spawn(async move {
+        let challenges = Arc::new(Challenges::new());
+        let stat = Arc::new(stat);
+        // Aborting the responder drops the set, which aborts every socket's task
+        let mut tasks = JoinSet::new();
+        for socket in sockets {
+            let challenges = challenges.clone();
+            let stat = stat.clone();
+            tasks.spawn(async move {
+                let mut buffer = [0; 1500];
+                loop {
+                    let (length, address) = match socket.recv_from(&mut buffer).await {
+                        Ok(received) => received,
+                        Err(err) => {
+                            println!("\x1b[38;5;11mWarning: Couldn't receive a query. Got err: {err}\x1b[0m");
+                            continue;
+                        }
+                    };
+                    let ip = address.ip().to_canonical();
+                    if let Some(response) = respond(&buffer[..length], ip, &challenges, &stat) {
+                        if let Err(err) = socket.send_to(&response, address).await {
+                            println!("\x1b[38;5;11mWarning: Couldn't answer the query from {address}. Got err: {err}\x1b[0m");
+                        }
+                    }
+                }
+            });
+        }
+        while tasks.join_next().await.is_some() {}
+    })
+}
+
+/// Asks the server at `address` for its full stat, which includes the names of online players
+pub async fn full_stat(address: SocketAddr) -> io::Result<Stat> {
+    let local: IpAddr = match address {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    let socket = UdpSocket::bind((local, 0)).await?;
+    socket.connect(address).await?;
+    // Servers only keep the low 4 bits of each byte
+    let session = (std::process::id() as i32 & 0x0F0F_0F0F).to_be_bytes();
+
+    let exchange = async {
+        let mut request = MAGIC.to_vec();
+        request.push(HANDSHAKE);
+        request.extend_from_slice(&session);
+        let response = exchange(&socket, &request, HANDSHAKE, session).await?;
+        let token = read_string(&mut response.as_slice())?;
+        // Sent back as a number, though servers write it as text
+        let token = token
+            .parse::<i64>()
+            .map_err(|_| invalid_data(&format!("invalid challenge token: '{token}'")))?
+            as i32;
+
+        let mut request = MAGIC.to_vec();
+        request.push(STAT);
+        request.extend_from_slice(&session);
+        request.extend_from_slice(&token.to_be_bytes());
+        request.extend_from_slice(&[0; 4]);
+        let response = exchange(&socket, &request, STAT, session).await?;
+        Stat::read_full(&response)
+    };
+
+    tokio::time::timeout(QUERY_TIMEOUT, exchange)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "the query took too long"))?
+}
+
+/// Sends `request` and returns the payload of the response, after its type and session
+async fn exchange(
+    socket: &UdpSocket,
+    request: &[u8],
+    kind: u8,
+    session: [u8; 4],
+) -> io::Result<Vec<u8>> {
+    socket.send(request).await?;
+    let mut buffer = vec![0; 65536];
+    let length = socket.recv(&mut buffer).await?;
+    buffer.truncate(length);
+    if buffer.len() < 5 || buffer[0] != kind || buffer[1..5] != session {
+        return Err(invalid_data("unexpected response"));
+    }
+    Ok(buffer.split_off(5))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responder_test() {
+        let stat = Stat {
+            motd: "A sleeping server".to_owned(),
+            game_type: "SMP".to_owned(),
+            version: "1.19.2".to_owned(),
+            map: "world".to_owned(),
+            num_players: 2,
+            max_players: 20,
+            host_port: 25565,
+            host_ip: "0.0.0.0".to_owned(),
+            players: vec!["alice".to_owned(), "bob".to_owned()],
+            ..Default::default()
+        };
+        let challenges = Challenges::new();
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let session = [1, 2, 3, 4];
+
+        let mut handshake = vec![0xFE, 0xFD, HANDSHAKE];
+        handshake.extend_from_slice(&session);
+        let response = respond(&handshake, ip, &challenges, &stat).unwrap();
+        assert_eq!(&response[..5], &[HANDSHAKE, 1, 2, 3, 4]);
+        let token: i32 = read_string(&mut &response[5..]).unwrap().parse().unwrap();
+
+        let mut full = vec![0xFE, 0xFD, STAT];
+        full.extend_from_slice(&session);
+        full.extend_from_slice(&(token ^ 1).to_be_bytes());
+        full.extend_from_slice(&[0; 4]);
+        assert_eq!(None, respond(&full, ip, &challenges, &stat));
+
+        full[7..11].copy_from_slice(&token.to_be_bytes());
+        let response = respond(&full, ip, &challenges, &stat).unwrap();
+        assert_eq!(&response[..5], &[STAT, 1, 2, 3, 4]);
+        assert_eq!(stat, Stat::read_full(&response[5..]).unwrap());
+
+        let response = respond(&full[..11], ip, &challenges, &stat).unwrap();
+        let mut basic = &response[5..];
+        assert_eq!("A sleeping server", read_string(&mut basic).unwrap());
+        assert_eq!("SMP", read_string(&mut basic).unwrap());
+    }
+}
@@ -0,0 +1,245 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Local, NaiveTime, TimeZone, Weekday};
+use clap::Args;
+
+use crate::duration::parse_timeout;
+
+/// A recurring period of time, like `fri 18:00-23:00` or `mon-fri,sun 02:00-04:30`.
+///
+/// Windows whose end comes before their start span midnight and belong to the day they start on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    /// Indexed by `Weekday::num_days_from_monday`
+    days: [bool; 7],
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl Window {
+    pub fn contains<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> bool {
+        let day = time.weekday();
+        let time_of_day = time.time();
+
+        if self.start < self.end {
+            self.is_active_on(day) && self.start <= time_of_day && time_of_day < self.end
+        } else {
+            (self.is_active_on(day) && self.start <= time_of_day)
+                || (self.is_active_on(day.pred()) && time_of_day < self.end)
+        }
+    }
+
+    fn is_active_on(&self, day: Weekday) -> bool {
+        self.days[day.num_days_from_monday() as usize]
+    }
+}
+
+impl std::str::FromStr for Window {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (days, hours) = value
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| format!("'{value}' should look like '<days> <HH:MM>-<HH:MM>'"))?;
+
+        let (start, end) = hours
+            .trim()
+            .split_once('-')
+            .ok_or_else(|| format!("'{hours}' should look like '<HH:MM>-<HH:MM>'"))?;
+
+        Ok(Self {
+            days: parse_days(days)?,
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        })
+    }
+}
+
+impl std::fmt::Display for Window {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+        let days: Vec<&str> = NAMES
+            .iter()
+            .zip(self.days)
+            .filter_map(|(name, active)| active.then_some(*name))
+            .collect();
+        write!(
+            f,
+            "{} {}-{}",
+            if days.len() == 7 {
+                "*".to_owned()
+            } else {
+                days.join(",")
+            },
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+fn parse_days(value: &str) -> Result<[bool; 7], String> {
+    let mut days = [false; 7];
+
+    for part in value.split(',') {
+        if part == "*" {
+            days = [true; 7];
+            continue;
+        }
+
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (parse_day(first)?, parse_day(last)?),
+            None => {
+                let day = parse_day(part)?;
+                (day, day)
+            }
+        };
+
+        let mut day = first;
+        loop {
+            days[day.num_days_from_monday() as usize] = true;
+            if day == last {
+                break;
+            }
+            day = day.succ();
+        }
+    }
+
+    Ok(days)
+}
+
+fn parse_day(value: &str) -> Result<Weekday, String> {
+    value
+        .parse::<Weekday>()
+        .map_err(|_| format!("'{value}' is not a day of the week"))
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .map_err(|_| format!("'{value}' is not a time of the form HH:MM"))
+}
+
+/// A window associated with a value, written as `<window>=<value>`
+#[derive(Debug, Clone)]
+pub struct Rule<T> {
+    pub window: Window,
+    pub value: T,
+}
+
+fn parse_rule<T>(
+    value: &str,
+    parse_value: impl Fn(&str) -> Result<T, String>,
+) -> Result<Rule<T>, String> {
+    let (window, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("'{value}' should look like '<days> <HH:MM>-<HH:MM>=<value>'"))?;
+    Ok(Rule {
+        window: window.parse()?,
+        value: parse_value(value)?,
+    })
+}
+
+/// Like `parse_rule` but the message is optional
+fn parse_refusal(value: &str) -> Result<Rule<Option<String>>, String> {
+    match value.split_once('=') {
+        Some((window, message)) => Ok(Rule {
+            window: window.parse()?,
+            value: Some(message.to_owned()),
+        }),
+        None => Ok(Rule {
+            window: value.parse()?,
+            value: None,
+        }),
+    }
+}
+
+/// When the minecraft server should be forced on, refuse wake-ups, or use a different timeout
+#[derive(Args, Debug)]
+pub struct Schedule {
+    /// keep the minecraft server running during this window, starting it if needed (can be repeated)
+    #[arg(long, value_name = "WINDOW")]
+    force_on: Vec<Window>,
+
+    /// refuse wake-ups from players during this window, optionally kicking them with a custom message (can be repeated)
+    #[arg(long, value_name = "WINDOW[=MESSAGE]", value_parser = parse_refusal)]
+    refuse_wake: Vec<Rule<Option<String>>>,
+
+    /// use a different inactivity timeout during this window (can be repeated, bare numbers are minutes)
+    #[arg(long, value_name = "WINDOW=DURATION", value_parser = |value: &str| parse_rule(value, parse_timeout))]
+    timeout_during: Vec<Rule<Duration>>,
+}
+
+impl Schedule {
+    /// The force-on window we are currently in, if any
+    pub fn forced_on(&self, now: &DateTime<Local>) -> Option<&Window> {
+        self.force_on.iter().find(|window| window.contains(now))
+    }
+
+    /// Returns `Some` with an optional custom message if wake-ups should be refused right now
+    pub fn wake_refusal(&self, now: &DateTime<Local>) -> Option<Option<&str>> {
+        self.refuse_wake
+            .iter()
+            .find(|rule| rule.window.contains(now))
+            .map(|rule| rule.value.as_deref())
+    }
+
+    /// The inactivity timeout that applies right now, if it differs from the default one
+    pub fn timeout(&self, now: &DateTime<Local>) -> Option<Duration> {
+        self.timeout_during
+            .iter()
+            .find(|rule| rule.window.contains(now))
+            .map(|rule| rule.value)
+    }
+
+    pub fn has_force_on(&self) -> bool {
+        !self.force_on.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, Utc};
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        // 2023-01-02 is a monday
+        Utc.from_utc_datetime(
+            &NaiveDate::from_ymd_opt(2023, 1, day)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn window_parse_test() {
+        let window: Window = "mon-wed,sat 18:00-23:30".parse().unwrap();
+        assert_eq!([true, true, true, false, false, true, false], window.days);
+        assert_eq!("mon,tue,wed,sat 18:00-23:30", window.to_string());
+
+        let window: Window = "sat-mon 00:00-01:00".parse().unwrap();
+        assert_eq!([true, false, false, false, false, true, true], window.days);
+
+        assert!("fri".parse::<Window>().is_err());
+        assert!("fri 18:00".parse::<Window>().is_err());
+        assert!("fry 18:00-19:00".parse::<Window>().is_err());
+    }
+
+    #[test]
+    fn window_contains_test() {
+        let window: Window = "fri 18:00-23:00".parse().unwrap();
+        assert!(window.contains(&at(6, 18, 0)));
+        assert!(window.contains(&at(6, 22, 59)));
+        assert!(!window.contains(&at(6, 23, 0)));
+        assert!(!window.contains(&at(7, 19, 0)));
+    }
+
+    #[test]
+    fn window_spanning_midnight_test() {
+        let window: Window = "sat 22:00-02:00".parse().unwrap();
+        assert!(window.contains(&at(7, 23, 0)));
+        assert!(window.contains(&at(8, 1, 0)));
+        assert!(!window.contains(&at(8, 3, 0)));
+        assert!(!window.contains(&at(7, 1, 0)));
+    }
+}
@@ -0,0 +1,568 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, FixedOffset, Local};
+use md5::{Digest, Md5};
+use tokio::{
+    fs,
+    io::{self, AsyncReadExt},
+    sync::watch,
+    task,
+};
+
+/// Date format used by the minecraft server in its ban lists
+const BAN_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+
+/// Files that trigger a reload of the lists when they change
+const WATCHED_FILES: [&str; 6] = [
+    "whitelist.json",
+    "ops.json",
+    "usercache.json",
+    "server.properties",
+    "banned-players.json",
+    "banned-ips.json",
+];
+
+/// How often the watched files are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub enum ListParseError {
+    ParseJson(PathBuf, serde_json::Error),
+    IO(PathBuf, io::Error),
+}
+
+impl std::fmt::Display for ListParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ParseJson(path, err) => {
+                write!(f, "{} contained invalid JSON: {err}", path.display())
+            }
+            Self::IO(path, err) => write!(f, "couldn't read {}: {err}", path.display()),
+        }
+    }
+}
+
+/// Everything the spoofer needs from the player lists of the minecraft server
+#[derive(Debug, Default, PartialEq)]
+pub struct ServerLists {
+    /// `None` if the whitelist isn't enforced
+    pub whitelist: Option<Whitelist>,
+    pub bans: Bans,
+}
+
+pub async fn parse_lists(
+    root_folder: &PathBuf,
+    whitelist: bool,
+) -> Result<ServerLists, ListParseError> {
+    Ok(ServerLists {
+        whitelist: if whitelist {
+            Some(parse_whitelist(root_folder).await?)
+        } else {
+            None
+        },
+        bans: parse_bans(root_folder).await?,
+    })
+}
+
+/// Spawns a task reloading the lists whenever one of their files changes.
+///
+/// If a new version of a file can't be parsed, the previous lists are kept.
+pub fn watch_lists(
+    root_folder: PathBuf,
+    whitelist: bool,
+    lists: ServerLists,
+) -> watch::Receiver<Arc<ServerLists>> {
+    let (sender, receiver) = watch::channel(Arc::new(lists));
+
+    task::spawn(async move {
+        let mut fingerprint = fingerprint(&root_folder).await;
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let new_fingerprint = self::fingerprint(&root_folder).await;
+            if new_fingerprint == fingerprint {
+                continue;
+            }
+            fingerprint = new_fingerprint;
+
+            if !reload_lists(&root_folder, whitelist, &sender).await {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Parses the lists again and sends them if they changed, keeping the previous ones if they can't be parsed.
+/// Returns `false` once nobody listens for them anymore.
+async fn reload_lists(
+    root_folder: &PathBuf,
+    whitelist: bool,
+    sender: &watch::Sender<Arc<ServerLists>>,
+) -> bool {
+    match parse_lists(root_folder, whitelist).await {
+        Ok(lists) => {
+            if **sender.borrow() != lists {
+                println!("\x1b[38;5;14mReloaded whitelist and ban lists\x1b[0m");
+                return sender.send(Arc::new(lists)).is_ok();
+            }
+        }
+        Err(err) => println!(
+            "\x1b[38;5;11mWarning: Couldn't reload the player lists, keeping the previous ones. Got err: {err}\x1b[0m"
+        ),
+    }
+    true
+}
+
+/// Modification time and size of each watched file, `None` if it doesn't exist
+async fn fingerprint(root_folder: &Path) -> Vec<Option<(SystemTime, u64)>> {
+    let mut fingerprint = Vec::with_capacity(WATCHED_FILES.len());
+    for file in WATCHED_FILES {
+        fingerprint.push(
+            fs::metadata(root_folder.join(file))
+                .await
+                .ok()
+                .and_then(|metadata| Some((metadata.modified().ok()?, metadata.len()))),
+        );
+    }
+    fingerprint
+}
+
+/// Players allowed to wake the server up
+#[derive(Debug, PartialEq)]
+pub struct Whitelist {
+    uuids: Vec<u128>,
+    /// Lowercase names from the whitelist, and from the user cache for whitelisted uuids
+    names: Vec<String>,
+    online_mode: bool,
+}
+
+impl Whitelist {
+    /// In online mode, players are matched by the uuid their client sent, or by name when there is none.
+    /// In offline mode, the uuid the server would give them is computed from their name.
+    pub fn contains(&self, uuid: Option<u128>, name: &str) -> bool {
+        let uuid = if self.online_mode {
+            uuid
+        } else {
+            Some(offline_uuid(name))
+        };
+
+        match uuid {
+            Some(uuid) if self.uuids.binary_search(&uuid).is_ok() => true,
+            Some(_) if self.online_mode => false,
+            _ => self.names.contains(&name.to_lowercase()),
+        }
+    }
+
+    pub fn online_mode(&self) -> bool {
+        self.online_mode
+    }
+}
+
+/// The uuid the minecraft server gives to `name` when `online-mode` is disabled
+pub fn offline_uuid(name: &str) -> u128 {
+    let mut bytes: [u8; 16] = Md5::digest(format!("OfflinePlayer:{name}")).into();
+    // Version 3 (name based, md5) and IETF variant
+    bytes[6] = bytes[6] & 0x0f | 0x30;
+    bytes[8] = bytes[8] & 0x3f | 0x80;
+    u128::from_be_bytes(bytes)
+}
+
+async fn parse_whitelist(root_folder: &PathBuf) -> Result<Whitelist, ListParseError> {
+    let mut whitelist_path = PathBuf::from(root_folder);
+    whitelist_path.push("whitelist.json");
+
+    let mut ops_path = PathBuf::from(root_folder);
+    ops_path.push("ops.json");
+
+    let mut entries = get_players_from_json(&whitelist_path).await?;
+    entries.append(&mut get_players_from_json(&ops_path).await?);
+
+    let mut uuids: Vec<u128> = entries.iter().filter_map(|(uuid, _)| *uuid).collect();
+    uuids.sort_unstable();
+    uuids.dedup();
+
+    let mut names: Vec<String> = entries
+        .into_iter()
+        .filter_map(|(_, name)| name.map(|name| name.to_lowercase()))
+        .collect();
+
+    // The user cache knows the names of players that were whitelisted by uuid only
+    match read_json_array(&root_folder.join("usercache.json")).await {
+        Ok(cache) => names.extend(cache.iter().filter_map(|entry| {
+            let uuid = parse_uuid(&entry["uuid"])?;
+            let name = entry["name"].as_str()?;
+            uuids
+                .binary_search(&uuid)
+                .is_ok()
+                .then(|| name.to_lowercase())
+        })),
+        Err(ListParseError::IO(_, err)) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => {
+            println!("\x1b[38;5;11mWarning: ignoring usercache.json. Got err: {err}\x1b[0m")
+        }
+    }
+
+    names.sort_unstable();
+    names.dedup();
+
+    let online_mode = match read_server_properties(root_folder).await {
+        Ok(properties) => properties.get("online-mode").map(String::as_str) != Some("false"),
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                println!(
+                    "\x1b[38;5;11mWarning: couldn't read server.properties, assuming online-mode is enabled. Got err: {err}\x1b[0m"
+                );
+            }
+            true
+        }
+    };
+
+    Ok(Whitelist {
+        uuids,
+        names,
+        online_mode,
+    })
+}
+
+/// Reads the `key=value` pairs of `server.properties`
+pub async fn read_server_properties(root_folder: &Path) -> io::Result<HashMap<String, String>> {
+    let content = fs::read_to_string(root_folder.join("server.properties")).await?;
+
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+        .collect())
+}
+
+async fn read_json_array(file_path: &Path) -> Result<Vec<serde_json::Value>, ListParseError> {
+    let mut file_content = String::new();
+    async {
+        fs::File::open(file_path)
+            .await?
+            .read_to_string(&mut file_content)
+            .await
+    }
+    .await
+    .map_err(|err| ListParseError::IO(file_path.to_owned(), err))?;
+
+    match serde_json::from_str(&file_content) {
+        Ok(parsed) => Ok(parsed),
+        Err(err) => Err(ListParseError::ParseJson(file_path.to_owned(), err)),
+    }
+}
+
+fn parse_uuid(value: &serde_json::Value) -> Option<u128> {
+    u128::from_str_radix(&value.as_str()?.replace('-', ""), 16).ok()
+}
+
+/// Reads the uuid and name of every entry. At least one of them is present.
+async fn get_players_from_json(
+    file_path: &Path,
+) -> Result<Vec<(Option<u128>, Option<String>)>, ListParseError> {
+    let objects = read_json_array(file_path).await?;
+
+    let mut players = Vec::with_capacity(objects.len());
+
+    for entry in objects {
+        match (parse_uuid(&entry["uuid"]), entry["name"].as_str()) {
+            (None, None) => println!(
+                "\x1b[38;5;11mWarning: couldn't parse {} because of this entry:\n{entry}\x1b[0m",
+                file_path.display()
+            ),
+            (uuid, name) => players.push((uuid, name.map(str::to_owned))),
+        };
+    }
+
+    Ok(players)
+}
+
+/// An entry of `banned-players.json` or `banned-ips.json`
+#[derive(Debug, PartialEq)]
+pub struct Ban<T> {
+    pub target: T,
+    reason: Option<String>,
+    /// `None` if the ban is permanent
+    expires: Option<DateTime<FixedOffset>>,
+}
+
+impl<T> Ban<T> {
+    fn is_active(&self, now: &DateTime<Local>) -> bool {
+        self.expires.is_none_or(|expires| expires > *now)
+    }
+
+    /// Mimics the messages the minecraft server kicks banned players with
+    fn kick_message(&self, header: &str) -> String {
+        let mut message = header.to_owned();
+        if let Some(ref reason) = self.reason {
+            message.push_str(&format!("\nReason: {reason}"));
+        }
+        if let Some(expires) = self.expires {
+            message.push_str(&format!(
+                "\nYour ban will be removed on {}",
+                expires.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+            ));
+        }
+        message
+    }
+}
+
+/// Banned player, identified by uuid and name
+#[derive(Debug, PartialEq)]
+pub struct BannedPlayer {
+    pub uuid: Option<u128>,
+    pub name: Option<String>,
+}
+
+/// The contents of `banned-players.json` and `banned-ips.json`
+#[derive(Debug, Default, PartialEq)]
+pub struct Bans {
+    players: Vec<Ban<BannedPlayer>>,
+    ips: Vec<Ban<IpAddr>>,
+}
+
+impl Bans {
+    /// Returns the kick message for the player if they or their IP are banned
+    pub fn check(&self, uuid: Option<u128>, name: &str, ip: IpAddr) -> Option<String> {
+        let now = Local::now();
+
+        if let Some(ban) = self.players.iter().find(|ban| {
+            ban.is_active(&now)
+                && (uuid.is_some() && ban.target.uuid == uuid
+                    || ban
+                        .target
+                        .name
+                        .as_ref()
+                        .is_some_and(|banned| banned.eq_ignore_ascii_case(name)))
+        }) {
+            return Some(ban.kick_message("You are banned from this server."));
+        }
+
+        self.ips
+            .iter()
+            .find(|ban| ban.is_active(&now) && ban.target == ip)
+            .map(|ban| ban.kick_message("Your IP address is banned from this server."))
+    }
+}
+
+/// Reads the ban lists in the root folder of the server. Missing files are treated as empty lists.
+async fn parse_bans(root_folder: &Path) -> Result<Bans, ListParseError> {
+    let players = read_ban_list(&root_folder.join("banned-players.json"), |entry| {
+        let player = BannedPlayer {
+            uuid: parse_uuid(&entry["uuid"]),
+            name: entry["name"].as_str().map(str::to_owned),
+        };
+        (player.uuid.is_some() || player.name.is_some()).then_some(player)
+    })
+    .await?;
+
+    let ips = read_ban_list(&root_folder.join("banned-ips.json"), |entry| {
+        entry["ip"].as_str()?.parse::<IpAddr>().ok()
+    })
+    .await?;
+
+    Ok(Bans { players, ips })
+}
+
+async fn read_ban_list<T>(
+    file_path: &Path,
+    parse_target: impl Fn(&serde_json::Value) -> Option<T>,
+) -> Result<Vec<Ban<T>>, ListParseError> {
+    let objects = match read_json_array(file_path).await {
+        Err(ListParseError::IO(_, err)) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok(vec![])
+        }
+        other => other?,
+    };
+
+    let mut bans = Vec::with_capacity(objects.len());
+
+    for entry in objects {
+        let expires = match entry["expires"].as_str() {
+            None | Some("forever") => Ok(None),
+            Some(date) => DateTime::parse_from_str(date, BAN_DATE_FORMAT).map(Some),
+        };
+
+        match (parse_target(&entry), expires) {
+            (Some(target), Ok(expires)) => bans.push(Ban {
+                target,
+                reason: entry["reason"].as_str().map(str::to_owned),
+                expires,
+            }),
+            _ => println!(
+                "\x1b[38;5;11mWarning: couldn't parse {} because of this entry:\n{entry}\x1b[0m",
+                file_path.display()
+            ),
+        }
+    }
+
+    Ok(bans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for the files of a test
+    async fn temp_dir(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("activitymanager-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path).await;
+        fs::create_dir_all(&path).await.unwrap();
+        path
+    }
+
+    #[test]
+    fn offline_uuid_test() {
+        assert_eq!(0xb50ad385829d3141a2167e7d7539ba7f, offline_uuid("Notch"));
+    }
+
+    #[test]
+    fn whitelist_matching_test() {
+        let whitelist = Whitelist {
+            uuids: vec![0x1234, offline_uuid("Steve")],
+            names: vec!["alex".to_owned()],
+            online_mode: true,
+        };
+        assert!(whitelist.contains(Some(0x1234), "Someone"));
+        assert!(!whitelist.contains(Some(0x5678), "Alex"));
+        assert!(whitelist.contains(None, "ALEX"));
+        assert!(!whitelist.contains(None, "Steve"));
+
+        let whitelist = Whitelist {
+            online_mode: false,
+            ..whitelist
+        };
+        assert!(whitelist.contains(Some(0x5678), "Steve"));
+        assert!(whitelist.contains(None, "Alex"));
+        assert!(!whitelist.contains(Some(0x1234), "Someone"));
+    }
+
+    #[tokio::test]
+    async fn bans_test() {
+        let root = temp_dir("bans").await;
+        let now = Local::now();
+        let date = |days: i64| {
+            (now + chrono::Duration::days(days))
+                .format(BAN_DATE_FORMAT)
+                .to_string()
+        };
+        fs::write(
+            root.join("banned-players.json"),
+            serde_json::json!([
+                { "uuid": "069a79f4-44e9-4726-a5be-fca90e38aaf5", "name": "Notch", "expires": "forever", "reason": "Griefing" },
+                { "name": "jeb_", "expires": date(1) },
+                { "name": "Dinnerbone", "expires": date(-1), "reason": "Expired" },
+                { "expires": "forever" },
+            ])
+            .to_string(),
+        )
+        .await
+        .unwrap();
+        fs::write(
+            root.join("banned-ips.json"),
+            serde_json::json!([
+                { "ip": "192.0.2.1", "expires": "forever", "reason": "Spam" },
+                { "ip": "192.0.2.2", "expires": date(-1) },
+                { "ip": "not an ip", "expires": "forever" },
+            ])
+            .to_string(),
+        )
+        .await
+        .unwrap();
+
+        let bans = parse_bans(&root).await.unwrap();
+        fs::remove_dir_all(&root).await.unwrap();
+        // Entries without a target are skipped
+        assert_eq!(3, bans.players.len());
+        assert_eq!(2, bans.ips.len());
+
+        let ip = IpAddr::from([127, 0, 0, 1]);
+        let notch = 0x069a79f444e94726a5befca90e38aaf5;
+        assert_eq!(
+            Some("You are banned from this server.\nReason: Griefing".to_owned()),
+            bans.check(Some(notch), "Someone", ip)
+        );
+        // Players without a uuid are matched by name
+        assert!(bans.check(None, "NOTCH", ip).is_some());
+        let temporary = bans.check(Some(0x1234), "jeb_", ip).unwrap();
+        assert!(
+            temporary.starts_with("You are banned from this server.\nYour ban will be removed on ")
+        );
+        assert!(bans.check(None, "Dinnerbone", ip).is_none());
+
+        assert_eq!(
+            Some("Your IP address is banned from this server.\nReason: Spam".to_owned()),
+            bans.check(None, "Someone", IpAddr::from([192, 0, 2, 1]))
+        );
+        assert!(bans
+            .check(None, "Someone", IpAddr::from([192, 0, 2, 2]))
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn missing_ban_lists_test() {
+        let root = temp_dir("missing-bans").await;
+        let bans = parse_bans(&root).await.unwrap();
+        fs::remove_dir_all(&root).await.unwrap();
+        assert_eq!(Bans::default(), bans);
+    }
+
+    #[tokio::test]
+    async fn reload_lists_test() {
+        let root = temp_dir("reload").await;
+        let whitelist = |names: &[&str]| {
+            serde_json::Value::from_iter(
+                names.iter().map(|name| serde_json::json!({ "name": name })),
+            )
+            .to_string()
+        };
+        fs::write(root.join("ops.json"), "[]").await.unwrap();
+        fs::write(root.join("whitelist.json"), whitelist(&["Alex"]))
+            .await
+            .unwrap();
+
+        let (sender, receiver) = watch::channel(Arc::new(parse_lists(&root, true).await.unwrap()));
+        let contains = |name| {
+            receiver
+                .borrow()
+                .whitelist
+                .as_ref()
+                .unwrap()
+                .contains(None, name)
+        };
+        assert!(contains("Alex"));
+
+        // Saved halfway through
+        fs::write(root.join("whitelist.json"), r#"[{ "name": "Alex" }, { "na"#)
+            .await
+            .unwrap();
+        assert!(reload_lists(&root, true, &sender).await);
+        assert!(contains("Alex"));
+
+        fs::write(root.join("whitelist.json"), whitelist(&["Alex", "Steve"]))
+            .await
+            .unwrap();
+        assert!(reload_lists(&root, true, &sender).await);
+        assert!(contains("Steve"));
+
+        drop(receiver);
+        fs::write(root.join("whitelist.json"), whitelist(&["Steve"]))
+            .await
+            .unwrap();
+        assert!(!reload_lists(&root, true, &sender).await);
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+}
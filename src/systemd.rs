@@ -0,0 +1,268 @@
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    os::{
+        fd::{FromRawFd, RawFd},
+        linux::net::SocketAddrExt,
+        unix::{
+            ffi::OsStrExt,
+            net::{SocketAddr, UnixDatagram},
+        },
+    },
+    sync::Arc,
+    time::Duration,
+};
+
+use socket2::Socket;
+use tokio::{io, net::TcpListener, task};
+
+/// The first file descriptor passed by systemd, after stdin, stdout and stderr
+const LISTEN_FDS_START: RawFd = 3;
+
+/// The variables systemd sets for us, taken out of the environment so the minecraft server doesn't use them too
+#[derive(Debug, Default)]
+pub struct Environment {
+    listen_pid: Option<String>,
+    listen_fds: Option<String>,
+    notify_socket: Option<OsString>,
+    watchdog_usec: Option<String>,
+    watchdog_pid: Option<String>,
+}
+
+impl Environment {
+    /// Must be called before any other thread is started, as changing the environment isn't thread safe
+    pub fn take() -> Self {
+        let environment = Self {
+            listen_pid: env::var("LISTEN_PID").ok(),
+            listen_fds: env::var("LISTEN_FDS").ok(),
+            notify_socket: env::var_os("NOTIFY_SOCKET"),
+            watchdog_usec: env::var("WATCHDOG_USEC").ok(),
+            watchdog_pid: env::var("WATCHDOG_PID").ok(),
+        };
+        for var in [
+            "LISTEN_PID",
+            "LISTEN_FDS",
+            "LISTEN_FDNAMES",
+            "NOTIFY_SOCKET",
+            "WATCHDOG_USEC",
+            "WATCHDOG_PID",
+        ] {
+            env::remove_var(var);
+        }
+        environment
+    }
+
+    /// Whether `pid` is ours, as the variables of another process may have been inherited from it
+    fn is_ours(pid: Option<&str>) -> bool {
+        pid.map(str::parse) == Some(Ok(std::process::id()))
+    }
+
+    /// Takes the number of sockets passed by systemd socket activation, if there are any for us
+    fn take_listen_fds(&mut self) -> io::Result<Option<RawFd>> {
+        let Some(fds) = self.listen_fds.take() else {
+            return Ok(None);
+        };
+        if !Self::is_ours(self.listen_pid.as_deref()) {
+            return Ok(None);
+        }
+        fds.parse().map(Some).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("LISTEN_FDS is not a number: '{fds}'"),
+            )
+        })
+    }
+
+    /// The timeout of the systemd watchdog, if it watches us
+    fn watchdog(&self) -> Option<Duration> {
+        if self.watchdog_pid.is_some() && !Self::is_ours(self.watchdog_pid.as_deref()) {
+            return None;
+        }
+        self.watchdog_usec
+            .as_deref()
+            .and_then(|usec| usec.parse().ok())
+            .map(Duration::from_micros)
+    }
+}
+
+/// Takes the listening sockets passed by systemd socket activation, if there are any for us
+pub fn take_listeners(environment: &mut Environment) -> io::Result<Option<Vec<TcpListener>>> {
+    let Some(fds) = environment.take_listen_fds()? else {
+        return Ok(None);
+    };
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + fds)
+        .map(|fd| {
+            // SAFETY: systemd passes us ownership of these file descriptors, and nothing else uses them
+            let socket = unsafe { Socket::from_raw_fd(fd) };
+            // Otherwise the minecraft server would inherit them
+            socket.set_cloexec(true)?;
+            socket.set_nonblocking(true)?;
+            TcpListener::from_std(socket.into())
+        })
+        .collect::<io::Result<Vec<_>>>()
+        .map(Some)
+}
+
+/// Reports the state of the manager to systemd (see `sd_notify(3)`). Does nothing when not run by systemd.
+pub struct Notifier {
+    socket: Option<UnixDatagram>,
+    watchdog: Option<Duration>,
+}
+
+impl Notifier {
+    pub fn new(environment: &Environment) -> Self {
+        let socket = environment.notify_socket.as_ref().and_then(|path| {
+            let path = path.as_bytes();
+            // Paths starting with '@' are in the abstract namespace
+            let address = match path.strip_prefix(b"@") {
+                Some(name) => SocketAddr::from_abstract_name(name),
+                None => SocketAddr::from_pathname(OsStr::from_bytes(path)),
+            };
+            let socket = address.and_then(|address| {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect_addr(&address)?;
+                Ok(socket)
+            });
+            match socket {
+                Ok(socket) => Some(socket),
+                Err(err) => {
+                    println!("\x1b[38;5;11mWarning: Couldn't connect to the systemd notification socket. Got err: {err}\x1b[0m");
+                    None
+                }
+            }
+        });
+
+        let watchdog = environment.watchdog();
+
+        Self { socket, watchdog }
+    }
+
+    /// Sends newline separated `KEY=VALUE` assignments
+    pub fn notify(&self, state: &str) {
+        if let Some(ref socket) = self.socket {
+            if let Err(err) = socket.send(state.as_bytes()) {
+                println!("\x1b[38;5;11mWarning: Couldn't notify systemd. Got err: {err}\x1b[0m");
+            }
+        }
+    }
+
+    pub fn status(&self, status: &str) {
+        self.notify(&format!("STATUS={status}"));
+    }
+
+    /// Pings the systemd watchdog at half its timeout for as long as the manager runs
+    pub fn spawn_watchdog(self: &Arc<Self>) {
+        if let Some(timeout) = self.watchdog {
+            let notifier = self.clone();
+            task::spawn(async move {
+                let mut interval = tokio::time::interval(timeout / 2);
+                loop {
+                    interval.tick().await;
+                    notifier.notify("WATCHDOG=1");
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notify_test() {
+        let (socket, systemd) = UnixDatagram::pair().unwrap();
+        let notifier = Notifier {
+            socket: Some(socket),
+            watchdog: None,
+        };
+
+        notifier.notify("READY=1");
+        notifier.status("Spoofing");
+        let mut buffer = [0; 64];
+        let length = systemd.recv(&mut buffer).unwrap();
+        assert_eq!(b"READY=1", &buffer[..length]);
+        let length = systemd.recv(&mut buffer).unwrap();
+        assert_eq!(b"STATUS=Spoofing", &buffer[..length]);
+
+        // Not being run by systemd isn't an error
+        Notifier {
+            socket: None,
+            watchdog: None,
+        }
+        .notify("READY=1");
+    }
+
+    #[test]
+    fn new_test() {
+        let name = format!("activitymanager-notify-{}", std::process::id());
+        let systemd =
+            UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(&name).unwrap()).unwrap();
+        let notifier = Notifier::new(&Environment {
+            notify_socket: Some(format!("@{name}").into()),
+            watchdog_usec: Some("2000000".to_owned()),
+            watchdog_pid: Some(std::process::id().to_string()),
+            ..Default::default()
+        });
+        assert_eq!(Some(Duration::from_secs(2)), notifier.watchdog);
+
+        notifier.notify("WATCHDOG=1");
+        let mut buffer = [0; 64];
+        let length = systemd.recv(&mut buffer).unwrap();
+        assert_eq!(b"WATCHDOG=1", &buffer[..length]);
+
+        // Not being run by systemd isn't an error
+        let notifier = Notifier::new(&Environment::default());
+        assert!(notifier.socket.is_none());
+        assert!(notifier.watchdog.is_none());
+    }
+
+    #[test]
+    fn watchdog_test() {
+        let watchdog = |pid: Option<&str>| {
+            Environment {
+                watchdog_usec: Some("500000".to_owned()),
+                watchdog_pid: pid.map(str::to_owned),
+                ..Default::default()
+            }
+            .watchdog()
+        };
+        assert_eq!(Some(Duration::from_millis(500)), watchdog(None));
+        assert_eq!(
+            Some(Duration::from_millis(500)),
+            watchdog(Some(&std::process::id().to_string()))
+        );
+        // Meant for another process
+        assert_eq!(None, watchdog(Some("1")));
+    }
+
+    #[test]
+    fn listen_fds_test() {
+        let pid = std::process::id().to_string();
+        let mut environment = Environment {
+            listen_pid: Some(pid.clone()),
+            listen_fds: Some("2".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(Some(2), environment.take_listen_fds().unwrap());
+        // The sockets can only be taken once
+        assert_eq!(None, environment.take_listen_fds().unwrap());
+
+        let listen_fds = |pid: &str, fds: &str| {
+            Environment {
+                listen_pid: Some(pid.to_owned()),
+                listen_fds: Some(fds.to_owned()),
+                ..Default::default()
+            }
+            .take_listen_fds()
+        };
+        // Meant for another process
+        assert_eq!(None, listen_fds("1", "2").unwrap());
+        assert!(listen_fds(&pid, "one").is_err());
+
+        assert!(take_listeners(&mut Environment::default())
+            .unwrap()
+            .is_none());
+    }
+}
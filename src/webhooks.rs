@@ -0,0 +1,322 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Local;
+use clap::Args;
+use reqwest::{Client, Url};
+use tokio::task;
+
+use crate::{
+    duration::parse_duration,
+    journal::{Entry, Event, EventKind},
+};
+
+/// HTTP endpoints notified of the lifecycle of the minecraft server
+#[derive(Args, Debug)]
+pub struct WebhookArgs {
+    /// POST the journal entry of lifecycle events as JSON to this url (can be repeated)
+    #[arg(long = "webhook", value_name = "URL")]
+    webhooks: Vec<Url>,
+
+    /// POST lifecycle events as a discord message to this discord webhook url (can be repeated)
+    #[arg(long = "discord-webhook", value_name = "URL")]
+    discord_webhooks: Vec<Url>,
+
+    /// events sent to webhooks (can be repeated)
+    #[arg(
+        long = "webhook-event",
+        value_name = "EVENT",
+        value_enum,
+        default_values_t = [EventKind::StartRequested, EventKind::BedrockStartRequested, EventKind::Ready, EventKind::IdleStop, EventKind::IdleFreeze, EventKind::Crash]
+    )]
+    webhook_events: Vec<EventKind>,
+
+    /// a request taking longer than this is given up on, and retried
+    #[arg(long, value_name = "DURATION", default_value = "10s", value_parser = parse_duration)]
+    webhook_timeout: Duration,
+
+    /// how many times a failed request is retried
+    #[arg(long, value_name = "COUNT", default_value_t = 3)]
+    webhook_retries: u32,
+}
+
+/// Which payload a webhook expects
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    /// The journal entry, with a `message` field for humans
+    Generic,
+    /// A discord message, without the addresses of players
+    Discord,
+}
+
+pub struct Webhooks {
+    client: Client,
+    args: WebhookArgs,
+}
+
+impl Webhooks {
+    pub fn new(args: WebhookArgs) -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(args.webhook_timeout)
+                .build()
+                .expect("the http client should be able to initialize"),
+            args,
+        }
+    }
+
+    /// Sends `event` to every webhook in the background, if it is one of the configured events
+    pub fn notify(self: &Arc<Self>, event: &Event) {
+        if !self.args.webhook_events.contains(&event.kind()) {
+            return;
+        }
+
+        let entry = Entry {
+            time: Local::now(),
+            event: event.clone(),
+        };
+
+        let targets = self
+            .args
+            .webhooks
+            .iter()
+            .map(|url| (url, Format::Generic))
+            .chain(
+                self.args
+                    .discord_webhooks
+                    .iter()
+                    .map(|url| (url, Format::Discord)),
+            );
+
+        for (url, format) in targets {
+            let webhooks = self.clone();
+            let url = url.clone();
+            let payload = payload(&entry, format);
+            task::spawn(async move { webhooks.send(url, payload).await });
+        }
+    }
+
+    async fn send(&self, url: Url, payload: serde_json::Value) {
+        let mut backoff = Duration::from_secs(1);
+
+        for attempt in 0..=self.args.webhook_retries {
+            if attempt != 0 {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+
+            let error = match self.client.post(url.clone()).json(&payload).send().await {
+                Ok(response) if response.status().is_success() => return,
+                // Retrying won't fix a request the endpoint doesn't accept
+                Ok(response)
+                    if response.status().is_client_error()
+                        && response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS =>
+                {
+                    println!(
+                        "\x1b[38;5;11mWarning: Webhook {} refused the notification with status {}\x1b[0m",
+                        url.host_str().unwrap_or_default(),
+                        response.status()
+                    );
+                    return;
+                }
+                Ok(response) => format!("status {}", response.status()),
+                Err(err) => err.without_url().to_string(),
+            };
+
+            println!(
+                "\x1b[38;5;11mWarning: Couldn't notify webhook {} (attempt {}/{}). Got err: {error}\x1b[0m",
+                url.host_str().unwrap_or_default(),
+                attempt + 1,
+                self.args.webhook_retries + 1
+            );
+        }
+    }
+}
+
+fn payload(entry: &Entry, format: Format) -> serde_json::Value {
+    match format {
+        Format::Generic => {
+            let mut payload = serde_json::to_value(entry).expect("entries should serialize");
+            payload["message"] = entry.event.to_string().into();
+            payload
+        }
+        Format::Discord => serde_json::json!({ "content": discord_message(&entry.event) }),
+    }
+}
+
+/// Messages meant for players, so they don't include addresses
+fn discord_message(event: &Event) -> String {
+    match event {
+        Event::StartRequested { player, .. } => format!("**{player}** is waking the server up"),
+        Event::BedrockStartRequested { .. } => {
+            "A bedrock player is waking the server up".to_owned()
+        }
+        Event::WakeRejected { player, reason, .. } => {
+            format!("**{player}** couldn't wake the server up ({reason})")
+        }
+        Event::BedrockWakeRejected { reason, .. } => {
+            format!("A bedrock player couldn't wake the server up ({reason})")
+        }
+        Event::StartAborted { .. } => "The server couldn't be started".to_owned(),
+        Event::ConsoleStart | Event::ScheduledStart { .. } => "The server is starting".to_owned(),
+        Event::Ready => ":green_circle: The server is up".to_owned(),
+        Event::IdleStop { idle, .. } | Event::IdleFreeze { idle, .. } => {
+            format!(":zzz: The server went to sleep after {idle} of inactivity")
+        }
+        Event::ManualStop { .. } | Event::Exit { .. } => "The server was stopped".to_owned(),
+        Event::Crash { .. } => ":warning: The server crashed".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    fn webhooks(webhooks: Vec<Url>) -> Arc<Webhooks> {
+        Arc::new(Webhooks::new(WebhookArgs {
+            webhooks,
+            discord_webhooks: Vec::new(),
+            webhook_events: vec![EventKind::StartRequested],
+            webhook_timeout: Duration::from_secs(5),
+            webhook_retries: 3,
+        }))
+    }
+
+    fn start_requested() -> Event {
+        Event::StartRequested {
+            player: "Notch".to_owned(),
+            uuid: None,
+            address: "127.0.0.1:54321".to_owned(),
+        }
+    }
+
+    /// A local HTTP endpoint answering with `statuses` in order, then with 200.
+    /// Returns its url and the bodies it received.
+    async fn stand_in(statuses: &[u16]) -> (Url, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let statuses = Arc::new(Mutex::new(statuses.to_vec()));
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+
+        let received = bodies.clone();
+        task::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let (statuses, bodies) = (statuses.clone(), received.clone());
+                task::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    // Requests can follow each other on the same connection
+                    loop {
+                        let mut content_length = 0;
+                        loop {
+                            let mut line = String::new();
+                            if stream.read_line(&mut line).await.unwrap() == 0 {
+                                return;
+                            }
+                            if line == "\r\n" {
+                                break;
+                            }
+                            if let Some((name, value)) = line.split_once(':') {
+                                if name.eq_ignore_ascii_case("content-length") {
+                                    content_length = value.trim().parse().unwrap();
+                                }
+                            }
+                        }
+                        let mut body = vec![0; content_length];
+                        stream.read_exact(&mut body).await.unwrap();
+                        bodies
+                            .lock()
+                            .unwrap()
+                            .push(serde_json::from_slice(&body).unwrap());
+
+                        let status = {
+                            let mut statuses = statuses.lock().unwrap();
+                            if statuses.is_empty() {
+                                200
+                            } else {
+                                statuses.remove(0)
+                            }
+                        };
+                        let response =
+                            format!("HTTP/1.1 {status} Stand-in\r\ncontent-length: 0\r\n\r\n");
+                        stream.write_all(response.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        (url, bodies)
+    }
+
+    #[tokio::test]
+    async fn retry_test() {
+        let (url, bodies) = stand_in(&[500]).await;
+        let payload = serde_json::json!({ "content": "hello" });
+        webhooks(vec![]).send(url, payload.clone()).await;
+        assert_eq!(vec![payload.clone(), payload], *bodies.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn client_error_test() {
+        let (url, bodies) = stand_in(&[400]).await;
+        webhooks(vec![]).send(url, serde_json::json!({})).await;
+        assert_eq!(1, bodies.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn notify_test() {
+        let (url, bodies) = stand_in(&[]).await;
+        let webhooks = webhooks(vec![url]);
+        // Not one of the configured events
+        webhooks.notify(&Event::ConsoleStart);
+        webhooks.notify(&start_requested());
+
+        for _ in 0..100 {
+            if !bodies.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(1, bodies.len());
+        let body = &bodies[0];
+        assert_eq!("start-requested", body["event"]);
+        assert_eq!("Notch", body["player"]);
+        assert_eq!("127.0.0.1:54321", body["address"]);
+        assert_eq!(serde_json::Value::Null, body["uuid"]);
+        assert_eq!(
+            "start requested by Notch from 127.0.0.1:54321",
+            body["message"]
+        );
+        assert!(body["time"].as_str().is_some());
+    }
+
+    #[test]
+    fn discord_payload_test() {
+        let entry = Entry {
+            time: Local::now(),
+            event: start_requested(),
+        };
+        assert_eq!(
+            serde_json::json!({ "content": "**Notch** is waking the server up" }),
+            payload(&entry, Format::Discord)
+        );
+
+        let rejected = Event::WakeRejected {
+            player: "Notch".to_owned(),
+            uuid: None,
+            address: "127.0.0.1:54321".to_owned(),
+            reason: "banned".to_owned(),
+        };
+        let message = discord_message(&rejected);
+        assert_eq!("**Notch** couldn't wake the server up (banned)", message);
+    }
+}